@@ -25,6 +25,32 @@ enum Action {
         #[arg(short, long = "output")]
         output: String,
     },
+    /// splits rules into one file per language
+    Split {
+        /// paths to the rules directories or files
+        paths: Vec<String>,
+
+        /// path to the output directory, files are named `<language>.yaml`
+        #[arg(short, long = "output")]
+        output: String,
+
+        /// put multi-language rules in `shared.yaml` instead of the file of
+        /// every language
+        #[arg(long = "shared")]
+        shared: bool,
+    },
+    /// lists the rules that match a query, e.g., `severity:ERROR lang:python`
+    Query {
+        /// the query
+        query: String,
+
+        /// paths to the rules directories or files
+        paths: Vec<String>,
+
+        /// write the matching rules to this file instead of listing their IDs
+        #[arg(short, long = "output")]
+        output: Option<String>,
+    },
     /// runs Semgrep
     Run {
         /// path(s) to the code to scan
@@ -51,6 +77,34 @@ enum Action {
         #[arg(short, long = "other")]
         extra: Option<Vec<String>>,
     },
+    /// runs the rule tests (`ruleid:` and `ok:` annotations) in paths
+    Test {
+        /// paths to the rules directories
+        paths: Vec<String>,
+    },
+    /// formats the rule files in paths
+    Fmt {
+        /// paths to the rules directories or files
+        paths: Vec<String>,
+
+        /// don't write the files, exit with 1 if any of them is not formatted
+        #[arg(long = "check")]
+        check: bool,
+
+        /// format files with comments and remove the comments. Without this
+        /// flag, files with comments are skipped
+        #[arg(long = "discard-comments")]
+        discard_comments: bool,
+    },
+    /// rewrites deprecated syntax in the rule files in path
+    Migrate {
+        /// path to the rules directory or file
+        path: String,
+
+        /// only report what would be migrated, don't write the files
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
 }
 
 fn main() {
@@ -67,6 +121,16 @@ fn main() {
             paths: rules,
             output,
         } => run_combine(&rules, &output),
+        Action::Split {
+            paths,
+            output,
+            shared,
+        } => run_split(&paths, &output, shared),
+        Action::Query {
+            query,
+            paths,
+            output,
+        } => run_query(&query, &paths, output),
         Action::Run {
             paths,
             config,
@@ -75,6 +139,13 @@ fn main() {
             format,
             extra,
         } => run(paths, &config, metrics, &output, &format, extra),
+        Action::Test { paths } => run_test(&paths),
+        Action::Fmt {
+            paths,
+            check,
+            discard_comments,
+        } => run_fmt(&paths, check, discard_comments),
+        Action::Migrate { path, dry_run } => run_migrate(&path, dry_run),
     };
 }
 
@@ -92,6 +163,49 @@ fn run_combine(paths: &Vec<String>, output: &str) {
     info!("Wrote the combined rule file to: {}", output);
 }
 
+// Split the rules in paths by language and write one file per language to the
+// output directory.
+fn run_split(paths: &[String], output: &str, shared: bool) {
+    let r: Vec<&str> = paths.iter().map(|s| s.as_str()).collect();
+    let rule_index = semgrep_rs::GenericRuleIndex::from_paths_simple(r).unwrap();
+
+    let mode = match shared {
+        true => semgrep_rs::SplitMode::Shared,
+        false => semgrep_rs::SplitMode::Duplicate,
+    };
+    fs::create_dir_all(output).expect("couldn't create the output directory");
+    for (language, rule_file) in rule_index.split_by_language(mode) {
+        let file = std::path::Path::new(output).join(format!("{}.yaml", language));
+        fs::write(&file, rule_file.to_string().unwrap()).expect("couldn't write the rule file");
+        info!(
+            "Wrote {} rules to: {}",
+            rule_file.rules.len(),
+            file.display()
+        );
+    }
+}
+
+// Print the IDs of the rules in paths that match the query or write the rules
+// to output.
+fn run_query(query: &str, paths: &[String], output: Option<String>) {
+    let r: Vec<&str> = paths.iter().map(|s| s.as_str()).collect();
+    let rule_index = semgrep_rs::GenericRuleIndex::from_paths_simple(r).unwrap();
+    let query = semgrep_rs::RuleQuery::parse(query).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+
+    match output {
+        Some(output) => {
+            let rule_file = rule_index.query_rules(&query);
+            fs::write(&output, rule_file.to_string().unwrap())
+                .expect("couldn't write the rule file");
+            info!("Wrote {} rules to: {}", rule_file.rules.len(), output);
+        }
+        None => rule_index.query(&query).iter().for_each(|id| println!("{}", id)),
+    }
+}
+
 // run semgrep with the given paths and config and return the results.
 fn run(
     paths: Vec<String>,
@@ -123,3 +237,69 @@ fn run(
     fs::write(output, bytes).expect("couldn't write the results file");
     info!("Wrote the results to: {}", output);
 }
+
+// Run the rule tests in paths and print the results. Exit with 1 if any test
+// fails.
+fn run_test(paths: &[String]) {
+    let p: Vec<&str> = paths.iter().map(|s| s.as_str()).collect();
+    let reports = semgrep_rs::run_rule_tests(p).unwrap();
+
+    for report in &reports {
+        print!("{}", report);
+    }
+    if !reports.iter().all(|r| r.passed()) {
+        std::process::exit(1);
+    }
+}
+
+// Format the rule files in paths. With check, only print the files that are
+// not formatted and exit with 1 if there are any. Files with comments are
+// skipped unless discard_comments is true because formatting removes them.
+fn run_fmt(paths: &[String], check: bool, discard_comments: bool) {
+    let mut unformatted = 0;
+    for path in paths {
+        for file in semgrep_rs::find_files_simple(path) {
+            let content = fs::read_to_string(&file).expect("couldn't read the rule file");
+            let formatted = match discard_comments {
+                true => semgrep_rs::format_yaml_discarding_comments(&content),
+                false => semgrep_rs::format_yaml(&content),
+            };
+            let formatted = match formatted {
+                Ok(f) => f,
+                Err(e) => {
+                    eprintln!("{}: {}", file, e);
+                    continue;
+                }
+            };
+            if formatted == content {
+                continue;
+            }
+            unformatted += 1;
+            if check {
+                println!("{} is not formatted", file);
+            } else {
+                fs::write(&file, formatted).expect("couldn't write the rule file");
+                info!("Formatted: {}", file);
+            }
+        }
+    }
+    if check && unformatted > 0 {
+        std::process::exit(1);
+    }
+}
+
+// Migrate the deprecated syntax in the rule files in path and print what was
+// (or would be) migrated and what needs to be fixed manually.
+fn run_migrate(path: &str, dry_run: bool) {
+    let results = semgrep_rs::migrate_path(path, !dry_run).unwrap();
+
+    for result in results {
+        println!("{}", result.file);
+        for item in &result.report.migrated {
+            println!("  migrated: {}", item);
+        }
+        for item in &result.report.unsupported {
+            println!("  manual: {}", item);
+        }
+    }
+}
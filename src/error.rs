@@ -1,5 +1,9 @@
 use std::{fmt, io, string};
 
+use crate::rules::load_report::LoadReport;
+use crate::rules::location::RuleSource;
+use crate::rules::schema::SchemaViolation;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -9,6 +13,14 @@ pub enum Error {
     JsonError(serde_json::Error),
     YamlError(serde_yaml::Error),
     Utf8Error(string::FromUtf8Error),
+    SchemaError(Vec<SchemaViolation>),
+    LoadError(LoadReport),
+    /// two rules have the same ID with `DuplicateStrategy::Fail`.
+    DuplicateRule {
+        id: String,
+        first: RuleSource,
+        second: RuleSource,
+    },
 }
 
 // impl std::error::Error for Error {}
@@ -21,6 +33,19 @@ impl fmt::Display for Error {
             Error::JsonError(e) => write!(f, "JSON Error: {}", e),
             Error::YamlError(e) => write!(f, "YAML error: {}", e),
             Error::Utf8Error(e) => write!(f, "Utf8 error: {}", e.utf8_error()),
+            Error::SchemaError(violations) => {
+                write!(f, "Schema error:")?;
+                for v in violations {
+                    write!(f, "\n{}", v)?;
+                }
+                Ok(())
+            }
+            Error::LoadError(report) => write!(f, "Load error: {}", report),
+            Error::DuplicateRule { id, first, second } => write!(
+                f,
+                "Duplicate rule ID: `{}` is defined in {}:{} and {}:{}",
+                id, first.file, first.start_line, second.file, second.start_line
+            ),
         }
     }
 }
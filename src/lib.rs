@@ -5,15 +5,48 @@ mod error;
 pub use error::{Error, Result};
 
 mod rules;
-pub use rules::generic_rule_index::GenericRuleIndex;
+#[cfg(feature = "archive")]
+pub use rules::archive::ArchiveFormat;
+pub use rules::builder::RuleBuilder;
+pub use rules::cache::IndexCache;
+pub use rules::diff::{ChangeKind, FieldChange, RuleDiff, RuleSetDiff};
+pub use rules::format::{format_yaml, format_yaml_discarding_comments, is_formatted};
+pub use rules::generic_rule_index::{
+    DuplicateRule, DuplicateStrategy, GenericRuleIndex, IndexOptions,
+};
+pub use rules::load_report::{LoadError, LoadErrorKind, LoadReport};
+pub use rules::location::RuleSource;
+pub use rules::migrate::{migrate_path, FileMigration, MigrationItem, MigrationReport};
+pub use rules::patterns::{PatternEntry, PatternKind};
 pub use rules::policy::{Policy, PolicyIndex};
-pub use rules::semgrep_generic_rule::{GenericRule, GenericRuleExt, GenericRuleFile};
+pub use rules::query::RuleQuery;
+pub use rules::rule::{
+    FixRegex, Join, JoinRef, JoinRename, KeyOrder, MetavariableAnalysis, MetavariableComparison,
+    MetavariablePattern, MetavariableRegex, Mode, OneOrMany, Paths, PatternClause, PatternOperator,
+    Rule, Severity, TaintSpec,
+};
+pub use rules::schema::SchemaViolation;
+pub use rules::semgrep_generic_rule::{
+    qualified_rule_id, GenericRule, GenericRuleExt, GenericRuleFieldsExt, GenericRuleFile,
+};
+pub use rules::split::{SplitMode, SHARED_BUCKET};
+#[cfg(feature = "watch")]
+pub use rules::watch::{IndexUpdate, IndexWatcher, WatchConfig};
 
 mod output;
-pub use output::cli_output_struct::CliOutput;
+pub use output::cli_output_struct::{CliMatch, CliOutput};
 
 mod run;
 pub use run::args::Args;
 pub use run::exec::is_installed;
 pub use run::output::Output;
 pub use run::output_format::OutputFormat;
+
+mod lint;
+pub use lint::checks::LintCheck;
+pub use lint::linter::{LintFinding, Linter};
+
+mod rule_test;
+pub use rule_test::annotations::{parse_annotations, Annotation, AnnotationKind};
+pub use rule_test::autofix::{apply_fixes, check_fixes, fixed_file_path, AutofixResult};
+pub use rule_test::runner::{run_rule_tests, RuleTest, RuleTestReport, RuleTestResult, TestLine};
@@ -0,0 +1,255 @@
+use std::fmt;
+
+use serde_yaml::Value;
+
+use crate::rules::location::escape_pointer;
+use crate::rules::metavariables::{find_metavariables, find_regex_metavariables};
+use crate::rules::rule::is_known_language;
+use crate::{Error, GenericRule, GenericRuleFieldsExt, Result};
+
+/// checks supported by the linter. Each check has a name (e.g.,
+/// `missing-category`) that can be used to enable or disable it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LintCheck {
+    /// the rule doesn't have `metadata.category`.
+    MissingCategory,
+    /// the rule's `message` doesn't use any metavariables.
+    MessageWithoutMetavariables,
+    /// `severity` is not one of INFO, WARNING or ERROR.
+    InvalidSeverity,
+    /// a language in `languages` is not supported by Semgrep.
+    UnknownLanguage,
+    /// a `pattern-not` is identical to a `pattern` in the same `patterns`.
+    RedundantPatternNot,
+    /// `message` or `fix` use a metavariable that no pattern binds.
+    UnboundMetavariable,
+}
+
+impl LintCheck {
+    /// return all the checks.
+    pub fn all() -> Vec<LintCheck> {
+        vec![
+            LintCheck::MissingCategory,
+            LintCheck::MessageWithoutMetavariables,
+            LintCheck::InvalidSeverity,
+            LintCheck::UnknownLanguage,
+            LintCheck::RedundantPatternNot,
+            LintCheck::UnboundMetavariable,
+        ]
+    }
+
+    /// return the name of the check.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintCheck::MissingCategory => "missing-category",
+            LintCheck::MessageWithoutMetavariables => "message-without-metavariables",
+            LintCheck::InvalidSeverity => "invalid-severity",
+            LintCheck::UnknownLanguage => "unknown-language",
+            LintCheck::RedundantPatternNot => "redundant-pattern-not",
+            LintCheck::UnboundMetavariable => "unbound-metavariable",
+        }
+    }
+
+    /// convert a check name to a LintCheck.
+    pub fn from_name(name: &str) -> Result<LintCheck> {
+        LintCheck::all()
+            .into_iter()
+            .find(|c| c.name() == name)
+            .ok_or_else(|| Error::new(format!("unknown lint check: {}", name)))
+    }
+
+    // run the check on a rule and return (JSON pointer in the rule, message)
+    // for every problem.
+    pub(crate) fn run(&self, rule: &GenericRule) -> Vec<(String, String)> {
+        match self {
+            LintCheck::MissingCategory => missing_category(rule),
+            LintCheck::MessageWithoutMetavariables => message_without_metavariables(rule),
+            LintCheck::InvalidSeverity => invalid_severity(rule),
+            LintCheck::UnknownLanguage => unknown_language(rule),
+            LintCheck::RedundantPatternNot => {
+                let mut results = Vec::new();
+                redundant_pattern_not(&Value::Mapping(rule.clone()), "", &mut results);
+                results
+            }
+            LintCheck::UnboundMetavariable => unbound_metavariables(rule),
+        }
+    }
+}
+
+impl fmt::Display for LintCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// ----- START checks
+
+fn missing_category(rule: &GenericRule) -> Vec<(String, String)> {
+    match rule.get("metadata") {
+        None => vec![(
+            String::new(),
+            "rule doesn't have `metadata.category`".to_string(),
+        )],
+        Some(metadata) => match metadata.get("category") {
+            Some(Value::String(c)) if !c.trim().is_empty() => vec![],
+            _ => vec![(
+                "/metadata".to_string(),
+                "rule doesn't have `metadata.category`".to_string(),
+            )],
+        },
+    }
+}
+
+fn message_without_metavariables(rule: &GenericRule) -> Vec<(String, String)> {
+    match rule.get("message").and_then(|m| m.as_str()) {
+        Some(message) if find_metavariables(message).is_empty() => vec![(
+            "/message".to_string(),
+            "`message` doesn't use any metavariables".to_string(),
+        )],
+        _ => vec![],
+    }
+}
+
+// compare the metavariables in `message` and `fix` with the ones bound by the
+// rule's positive patterns (`pattern`, `pattern-inside` and `pattern-regex`).
+// Named capture groups in `metavariable-regex` also bind metavariables.
+// Exclusions and join conditions don't bind anything.
+fn unbound_metavariables(rule: &GenericRule) -> Vec<(String, String)> {
+    let mut bound = rule.get_metavariables();
+    metavariable_regex_groups(&Value::Mapping(rule.clone()), &mut bound);
+
+    let mut results = Vec::new();
+    for field in ["message", "fix"] {
+        let text = match rule.get(field).and_then(|v| v.as_str()) {
+            Some(t) => t,
+            None => continue,
+        };
+        for mv in find_metavariables(text) {
+            if !bound.contains(&mv) {
+                results.push((
+                    format!("/{}", field),
+                    format!("`{}` uses `{}` but no pattern binds it", field, mv),
+                ));
+            }
+        }
+    }
+    results
+}
+
+fn metavariable_regex_groups(value: &Value, bound: &mut Vec<String>) {
+    match value {
+        Value::Mapping(map) => {
+            if let Some(regex) = map
+                .get("metavariable-regex")
+                .and_then(|m| m.get("regex"))
+                .and_then(|r| r.as_str())
+            {
+                bound.extend(find_regex_metavariables(regex));
+            }
+            for (_, child) in map {
+                metavariable_regex_groups(child, bound);
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                metavariable_regex_groups(item, bound);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn invalid_severity(rule: &GenericRule) -> Vec<(String, String)> {
+    match rule.get("severity") {
+        None => vec![],
+        Some(Value::String(s)) if ["INFO", "WARNING", "ERROR"].contains(&s.as_str()) => vec![],
+        Some(severity) => vec![(
+            "/severity".to_string(),
+            format!(
+                "severity `{}` is not one of INFO, WARNING or ERROR",
+                scalar_to_string(severity)
+            ),
+        )],
+    }
+}
+
+fn unknown_language(rule: &GenericRule) -> Vec<(String, String)> {
+    let languages = match rule.get("languages").and_then(|l| l.as_sequence()) {
+        Some(l) => l,
+        None => return vec![],
+    };
+    languages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, lang)| {
+            let name = scalar_to_string(lang);
+            match is_known_language(&name) {
+                true => None,
+                false => Some((
+                    format!("/languages/{}", i),
+                    format!("unknown language `{}`", name),
+                )),
+            }
+        })
+        .collect()
+}
+
+// walk the rule and look for `patterns` with a `pattern-not` that has the
+// same value as a `pattern`.
+fn redundant_pattern_not(value: &Value, pointer: &str, results: &mut Vec<(String, String)>) {
+    match value {
+        Value::Mapping(map) => {
+            for (key, child) in map {
+                let key = scalar_to_string(key);
+                let child_pointer = format!("{}/{}", pointer, escape_pointer(&key));
+                if key == "patterns" {
+                    if let Some(items) = child.as_sequence() {
+                        check_patterns(items, &child_pointer, results);
+                    }
+                }
+                redundant_pattern_not(child, &child_pointer, results);
+            }
+        }
+        Value::Sequence(items) => {
+            for (i, item) in items.iter().enumerate() {
+                redundant_pattern_not(item, &format!("{}/{}", pointer, i), results);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_patterns(items: &[Value], pointer: &str, results: &mut Vec<(String, String)>) {
+    let patterns: Vec<&str> = items
+        .iter()
+        .filter_map(|i| i.get("pattern").and_then(|p| p.as_str()))
+        .map(|p| p.trim())
+        .collect();
+
+    for (i, item) in items.iter().enumerate() {
+        if let Some(not) = item.get("pattern-not").and_then(|p| p.as_str()) {
+            if patterns.contains(&not.trim()) {
+                results.push((
+                    format!("{}/{}", pointer, i),
+                    format!("`pattern-not` is identical to a `pattern`: {}", not.trim()),
+                ));
+            }
+        }
+    }
+}
+
+// ----- END checks
+
+// return a scalar as a string. Used for keys and values that are not always
+// strings (e.g., `severity: 1`).
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Null => "null".to_string(),
+        v => serde_yaml::to_string(v)
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default(),
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::checks::LintCheck;
+use crate::rules::location::YamlLocations;
+use crate::utils::read_file_to_string;
+use crate::{GenericRule, GenericRuleExt, GenericRuleFile, GenericRuleIndex, Result};
+
+// ----- START LintFinding
+
+/// a problem found by the linter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintFinding {
+    /// the check that reported the problem.
+    pub check: LintCheck,
+    /// ID of the rule. Empty if the rule doesn't have an ID.
+    pub rule_id: String,
+    /// the file that contains the rule, None if it's not known.
+    pub file: Option<String>,
+    /// JSON pointer to the problem inside the rule, e.g., `/metadata`. Empty
+    /// if the problem is with the whole rule.
+    pub path: String,
+    /// line of the problem in the file (starts at 1), None if not known.
+    pub line: Option<usize>,
+    /// column of the problem in the file (starts at 1), None if not known.
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for LintFinding {
+    // file:line:column: rule_id: message [check]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+            if let (Some(line), Some(column)) = (self.line, self.column) {
+                write!(f, "{}:{}:", line, column)?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {} [{}]", self.rule_id, self.message, self.check)
+    }
+}
+
+// ----- END LintFinding
+
+// ----- START Linter
+
+/// runs a set of checks over rules. All checks are enabled by default.
+#[derive(Clone, Debug)]
+pub struct Linter {
+    checks: BTreeSet<LintCheck>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Linter::new()
+    }
+}
+
+impl Linter {
+    /// create a new Linter with all checks enabled.
+    pub fn new() -> Linter {
+        Linter {
+            checks: LintCheck::all().into_iter().collect(),
+        }
+    }
+
+    /// create a new Linter with only the provided checks enabled.
+    pub fn with_checks(checks: Vec<LintCheck>) -> Linter {
+        Linter {
+            checks: checks.into_iter().collect(),
+        }
+    }
+
+    /// enable a check.
+    pub fn enable(&mut self, check: LintCheck) {
+        self.checks.insert(check);
+    }
+
+    /// disable a check.
+    pub fn disable(&mut self, check: LintCheck) {
+        self.checks.remove(&check);
+    }
+
+    /// enable a check by name (e.g., `missing-category`).
+    pub fn enable_by_name(&mut self, name: &str) -> Result<()> {
+        LintCheck::from_name(name).map(|c| self.enable(c))
+    }
+
+    /// disable a check by name (e.g., `missing-category`).
+    pub fn disable_by_name(&mut self, name: &str) -> Result<()> {
+        LintCheck::from_name(name).map(|c| self.disable(c))
+    }
+
+    /// return true if the check is enabled.
+    pub fn is_enabled(&self, check: LintCheck) -> bool {
+        self.checks.contains(&check)
+    }
+
+    /// return the enabled checks.
+    pub fn get_checks(&self) -> Vec<LintCheck> {
+        self.checks.iter().cloned().collect()
+    }
+
+    /// lint one rule. `rule_id` and `file` are copied to the findings.
+    pub fn lint_rule(
+        &self,
+        rule: &GenericRule,
+        rule_id: &str,
+        file: Option<&str>,
+    ) -> Vec<LintFinding> {
+        let mut findings: Vec<LintFinding> = Vec::new();
+        for check in &self.checks {
+            for (path, message) in check.run(rule) {
+                findings.push(LintFinding {
+                    check: *check,
+                    rule_id: rule_id.to_string(),
+                    file: file.map(|f| f.to_string()),
+                    path,
+                    line: None,
+                    column: None,
+                    message,
+                });
+            }
+        }
+        findings
+    }
+
+    /// lint all the rules in a rule file. `file` is the path of the file (if
+    /// any) and is only used in the findings.
+    pub fn lint_rule_file(
+        &self,
+        rule_file: &GenericRuleFile,
+        file: Option<&str>,
+    ) -> Vec<LintFinding> {
+        lint_rules(self, rule_file, file, None)
+    }
+
+    /// lint the rules in a YAML string. The findings include line and column.
+    pub fn lint_yaml(&self, yaml: &str, file: Option<&str>) -> Result<Vec<LintFinding>> {
+        let rule_file = GenericRuleFile::from_yaml(yaml)?;
+        let locations = YamlLocations::from_yaml(yaml);
+        Ok(lint_rules(self, &rule_file, file, Some(&locations)))
+    }
+
+    /// read a rule file and lint its rules.
+    pub fn lint_file(&self, file: &str) -> Result<Vec<LintFinding>> {
+        let content = read_file_to_string(file)?;
+        self.lint_yaml(&content, Some(file))
+    }
+
+    /// lint all the rules in the index. Findings are sorted by rule ID. If a
+    /// rule was read from a file, the findings include the file, line and
+    /// column. The positions are the ones captured when the file was loaded
+    /// so they are correct for rules loaded from git revisions or archives.
+    pub fn lint_index(&self, ri: &GenericRuleIndex) -> Vec<LintFinding> {
+        let mut ids = ri.get_ids();
+        ids.sort();
+
+        let mut findings: Vec<LintFinding> = Vec::new();
+        for id in &ids {
+            let rule = match ri.get_index().get(id) {
+                Some(r) => r,
+                None => continue,
+            };
+            let source = match ri.get_source(id) {
+                Some(s) => s,
+                None => {
+                    findings.extend(self.lint_rule(rule, id, None));
+                    continue;
+                }
+            };
+            for mut finding in self.lint_rule(rule, id, Some(&source.file)) {
+                match ri.get_position(id, &finding.path) {
+                    Some(pos) => {
+                        finding.line = Some(pos.line);
+                        finding.column = Some(pos.column);
+                    }
+                    None => finding.line = Some(source.start_line),
+                }
+                findings.push(finding);
+            }
+        }
+        findings
+    }
+}
+
+// ----- END Linter
+
+fn lint_rules(
+    linter: &Linter,
+    rule_file: &GenericRuleFile,
+    file: Option<&str>,
+    locations: Option<&YamlLocations>,
+) -> Vec<LintFinding> {
+    let mut findings: Vec<LintFinding> = Vec::new();
+
+    for (index, rule) in rule_file.rules.iter().enumerate() {
+        let id = rule.get_id().unwrap_or_default();
+        for mut finding in linter.lint_rule(rule, id, file) {
+            let pointer = format!("/rules/{}{}", index, finding.path);
+            if let Some(pos) = locations.and_then(|l| l.get(&pointer)) {
+                finding.line = Some(pos.line);
+                finding.column = Some(pos.column);
+            }
+            findings.push(finding);
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINT_FILE: &str = "tests/lint/lint-problems.yaml";
+
+    fn checks(findings: &[LintFinding], rule_id: &str) -> Vec<LintCheck> {
+        findings
+            .iter()
+            .filter(|f| f.rule_id == rule_id)
+            .map(|f| f.check)
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_file() {
+        let findings = Linter::new().lint_file(LINT_FILE).unwrap();
+
+        assert_eq!(
+            checks(&findings, "no-category"),
+            vec![
+                LintCheck::MissingCategory,
+                LintCheck::MessageWithoutMetavariables
+            ]
+        );
+        assert_eq!(
+            checks(&findings, "bad-severity-and-language"),
+            vec![LintCheck::InvalidSeverity, LintCheck::UnknownLanguage]
+        );
+        assert_eq!(
+            checks(&findings, "redundant-not"),
+            vec![LintCheck::RedundantPatternNot]
+        );
+        assert!(checks(&findings, "clean").is_empty());
+
+        let unbound: Vec<&str> = findings
+            .iter()
+            .filter(|f| f.check == LintCheck::UnboundMetavariable)
+            .map(|f| f.message.as_str())
+            .collect();
+        assert_eq!(
+            unbound,
+            vec![
+                "`message` uses `$LEN` but no pattern binds it",
+                "`fix` uses `$SIZE` but no pattern binds it",
+                "`message` uses `$ARG` but no pattern binds it",
+                "`message` uses `$X` but no pattern binds it",
+            ]
+        );
+        // `$ARG` only appears in `pattern-not` which doesn't bind it.
+        assert_eq!(
+            checks(&findings, "unbound-in-exclusion"),
+            vec![LintCheck::UnboundMetavariable]
+        );
+        // the same for a `pattern` nested inside `pattern-not`.
+        assert_eq!(
+            checks(&findings, "unbound-in-nested-exclusion"),
+            vec![LintCheck::UnboundMetavariable]
+        );
+
+        // check the locations.
+        let language = findings
+            .iter()
+            .find(|f| f.check == LintCheck::UnknownLanguage)
+            .unwrap();
+        assert_eq!(language.file.as_deref(), Some(LINT_FILE));
+        assert_eq!(language.path, "/languages/1");
+        assert_eq!((language.line, language.column), (Some(21), Some(7)));
+
+        let not = findings
+            .iter()
+            .find(|f| f.check == LintCheck::RedundantPatternNot)
+            .unwrap();
+        assert_eq!(not.path, "/patterns/1");
+        assert_eq!(not.line, Some(29));
+    }
+
+    #[test]
+    fn test_disable_checks() {
+        let mut linter = Linter::new();
+        linter.disable(LintCheck::MissingCategory);
+        linter
+            .disable_by_name("message-without-metavariables")
+            .unwrap();
+        assert!(linter.disable_by_name("not-a-check").is_err());
+
+        let findings = linter.lint_file(LINT_FILE).unwrap();
+        assert!(checks(&findings, "no-category").is_empty());
+
+        let linter = Linter::with_checks(vec![LintCheck::UnknownLanguage]);
+        let findings = linter.lint_file(LINT_FILE).unwrap();
+        assert_eq!(findings.len(), 1);
+    }
+
+    #[test]
+    fn test_lint_index() {
+        let ri = GenericRuleIndex::from_path_simple("tests/rules/cpp").unwrap();
+        let findings = Linter::new().lint_index(&ri);
+
+        // the messages in these rules don't use metavariables.
+        assert_eq!(
+            findings
+                .iter()
+                .map(|f| f.rule_id.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["memcpy-insecure-use", "snprintf-insecure-use"]
+        );
+        // the findings point back at the rule files.
+        let memcpy = &findings[0];
+        assert_eq!(
+            memcpy.file.as_deref(),
+            Some("tests/rules/cpp/memcpy-insecure-use.yaml")
+        );
+        assert_eq!((memcpy.line, memcpy.column), (Some(7), Some(3)));
+    }
+}
@@ -0,0 +1,159 @@
+// Parse the `ruleid:`, `ok:`, `todoruleid:` and `todook:` annotations in rule
+// test targets. The format is the same as `semgrep --test`:
+//
+// // ruleid: rule-id-1, rule-id-2
+// code_that_should_match();
+//
+// Each annotation applies to the line after it.
+
+/// comment prefixes that can start an annotation.
+const COMMENT_PREFIXES: [&str; 5] = ["#", "//", "<!--", "(*", "/*"];
+/// strings that end a comment and should be removed from the rule IDs.
+const COMMENT_SUFFIXES: [&str; 3] = ["-->", "*)", "*/"];
+
+/// the kind of an annotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnnotationKind {
+    /// `ruleid:`, the rule should match the next line.
+    RuleId,
+    /// `ok:`, the rule should not match the next line.
+    Ok,
+    /// `todoruleid:`, the rule should match the next line but doesn't yet.
+    TodoRuleId,
+    /// `todook:`, the rule matches the next line but it shouldn't.
+    TodoOk,
+}
+
+impl AnnotationKind {
+    /// return the annotation keyword without the `:`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnotationKind::RuleId => "ruleid",
+            AnnotationKind::Ok => "ok",
+            AnnotationKind::TodoRuleId => "todoruleid",
+            AnnotationKind::TodoOk => "todook",
+        }
+    }
+
+    /// return true if the rule is currently expected to match the annotated
+    /// line. `todook` is a known false positive so it's expected to match.
+    /// `todoruleid` is a known false negative so it's not.
+    pub fn expects_match(&self) -> bool {
+        matches!(self, AnnotationKind::RuleId | AnnotationKind::TodoOk)
+    }
+
+    fn all() -> [AnnotationKind; 4] {
+        [
+            AnnotationKind::RuleId,
+            AnnotationKind::Ok,
+            AnnotationKind::TodoRuleId,
+            AnnotationKind::TodoOk,
+        ]
+    }
+}
+
+/// an annotation in a test target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    pub kind: AnnotationKind,
+    /// the rule IDs in the annotation.
+    pub rule_ids: Vec<String>,
+    /// the annotated line (starts at 1). This is the line after the comment.
+    pub line: usize,
+}
+
+/// parse all the annotations in the contents of a test target.
+pub fn parse_annotations(content: &str) -> Vec<Annotation> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            parse_line(line).map(|(kind, rule_ids)| Annotation {
+                kind,
+                rule_ids,
+                // i starts at 0 and the annotation is for the next line.
+                line: i + 2,
+            })
+        })
+        .collect()
+}
+
+// return the annotation in a line, if any.
+fn parse_line(line: &str) -> Option<(AnnotationKind, Vec<String>)> {
+    for prefix in COMMENT_PREFIXES {
+        let mut rest = line;
+        while let Some(start) = rest.find(prefix) {
+            let after = rest[start + prefix.len()..].trim_start_matches(' ');
+            for kind in AnnotationKind::all() {
+                if let Some(ids) = after
+                    .strip_prefix(kind.as_str())
+                    .and_then(|a| a.strip_prefix(':'))
+                {
+                    return Some((kind, parse_rule_ids(ids)));
+                }
+            }
+            rest = &rest[start + prefix.len()..];
+        }
+    }
+    None
+}
+
+// split the comma-separated rule IDs and remove the end of the comment.
+fn parse_rule_ids(ids: &str) -> Vec<String> {
+    let mut ids = ids.trim();
+    for suffix in COMMENT_SUFFIXES {
+        if let Some(stripped) = ids.strip_suffix(suffix) {
+            ids = stripped.trim();
+        }
+    }
+    ids.split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_annotations() {
+        let content = "\
+// ruleid: rule-1
+strcpy(a, b);
+#ok:rule-1,rule-2
+safe();
+<!-- todoruleid: rule-3 -->
+missed();
+x = 1; // todook: rule-1
+fp();
+// this ok: is not an annotation
+";
+        let annotations = parse_annotations(content);
+        assert_eq!(
+            annotations,
+            vec![
+                Annotation {
+                    kind: AnnotationKind::RuleId,
+                    rule_ids: vec!["rule-1".to_string()],
+                    line: 2
+                },
+                Annotation {
+                    kind: AnnotationKind::Ok,
+                    rule_ids: vec!["rule-1".to_string(), "rule-2".to_string()],
+                    line: 4
+                },
+                Annotation {
+                    kind: AnnotationKind::TodoRuleId,
+                    rule_ids: vec!["rule-3".to_string()],
+                    line: 6
+                },
+                Annotation {
+                    kind: AnnotationKind::TodoOk,
+                    rule_ids: vec!["rule-1".to_string()],
+                    line: 8
+                },
+            ]
+        );
+    }
+}
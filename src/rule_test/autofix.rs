@@ -0,0 +1,268 @@
+// Apply the fixes reported by Semgrep to the contents of a test target and
+// compare the result with the expected file. Similar to `semgrep --test`, the
+// expected file of `dir/foo.py` is `dir/foo.fixed.py`.
+
+use std::fmt;
+use std::path::Path;
+
+use regex::Regex;
+use similar::TextDiff;
+
+use crate::output::cli_output_struct::CliMatch;
+use crate::utils::read_file_to_string;
+use crate::{Error, Result};
+
+/// the result of comparing a fixed test target with its `.fixed` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AutofixResult {
+    /// the test target.
+    pub target: String,
+    /// the file with the expected fixes, e.g., `foo.fixed.py`.
+    pub fixed_file: String,
+    /// unified diff between the expected file and the target after applying
+    /// the fixes. None if they are the same.
+    pub diff: Option<String>,
+}
+
+impl AutofixResult {
+    /// return true if the fixed target is the same as the expected file.
+    pub fn passed(&self) -> bool {
+        self.diff.is_none()
+    }
+}
+
+impl fmt::Display for AutofixResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.diff {
+            None => writeln!(f, "PASS autofix {}", self.target),
+            Some(diff) => {
+                writeln!(f, "FAIL autofix {} != {}", self.target, self.fixed_file)?;
+                write!(f, "{}", diff)
+            }
+        }
+    }
+}
+
+/// return the path of the expected autofix file of a test target.
+/// `dir/foo.py` -> `dir/foo.fixed.py`.
+pub fn fixed_file_path(target: &str) -> String {
+    let path = Path::new(target);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.fixed.{}", stem, ext.to_string_lossy()),
+        None => format!("{}.fixed", stem),
+    };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+/// apply the fixes in the matches to content and return the fixed content.
+/// `fix` and `fix_regex` replace the matched range and `fixed_lines` replaces
+/// the matched lines. Matches without a fix are ignored. If two fixes overlap,
+/// only the first one is applied (like Semgrep).
+pub fn apply_fixes(content: &str, matches: &[&CliMatch]) -> Result<String> {
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+    for m in matches {
+        if let Some(edit) = fix_edit(content, m)? {
+            edits.push(edit);
+        }
+    }
+    edits.sort_by_key(|e| (e.0, e.1));
+
+    let mut fixed = String::with_capacity(content.len());
+    let mut last = 0;
+    for (start, end, replacement) in edits {
+        // skip overlapping fixes.
+        if start < last {
+            continue;
+        }
+        fixed.push_str(&content[last..start]);
+        fixed.push_str(&replacement);
+        last = end;
+    }
+    fixed.push_str(&content[last..]);
+    Ok(fixed)
+}
+
+/// apply the fixes to the test target and compare it with the expected file.
+/// Returns None if the target doesn't have a `.fixed` file.
+pub fn check_fixes(target: &str, matches: &[&CliMatch]) -> Result<Option<AutofixResult>> {
+    let fixed_file = fixed_file_path(target);
+    if !Path::new(&fixed_file).is_file() {
+        return Ok(None);
+    }
+
+    let actual = apply_fixes(&read_file_to_string(target)?, matches)?;
+    let expected = read_file_to_string(&fixed_file)?;
+
+    let diff = match actual == expected {
+        true => None,
+        false => Some(
+            TextDiff::from_lines(&expected, &actual)
+                .unified_diff()
+                .header(&fixed_file, target)
+                .to_string(),
+        ),
+    };
+
+    Ok(Some(AutofixResult {
+        target: target.to_string(),
+        fixed_file,
+        diff,
+    }))
+}
+
+// return the (start, end, replacement) edit of a match, if it has a fix.
+fn fix_edit(content: &str, m: &CliMatch) -> Result<Option<(usize, usize, String)>> {
+    let (start, end) = (m.start.offset as usize, m.end.offset as usize);
+    if m.start.offset < 0
+        || start > end
+        || end > content.len()
+        || !content.is_char_boundary(start)
+        || !content.is_char_boundary(end)
+    {
+        return Error::wrap_string(format!(
+            "invalid match range {}-{} in {}",
+            m.start.offset, m.end.offset, m.path
+        ));
+    }
+
+    if let Some(fix) = &m.extra.fix {
+        return Ok(Some((start, end, fix.clone())));
+    }
+
+    if let Some(fix_regex) = &m.extra.fix_regex {
+        let re = Regex::new(&fix_regex.regex)
+            .map_err(|e| Error::new(format!("invalid fix-regex: {}", e)))?;
+        // count is the maximum number of replacements, 0 replaces all of them.
+        let count = fix_regex.count.unwrap_or(0).max(0) as usize;
+        let replacement = python_replacement(&fix_regex.replacement);
+        let fixed = re.replacen(&content[start..end], count, replacement.as_str());
+        return Ok(Some((start, end, fixed.to_string())));
+    }
+
+    if let Some(lines) = &m.extra.fixed_lines {
+        // replace everything from the start of the first line to the end of
+        // the last line of the match.
+        let line_start = content[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = content[end..]
+            .find('\n')
+            .map(|i| end + i)
+            .unwrap_or(content.len());
+        return Ok(Some((line_start, line_end, lines.join("\n"))));
+    }
+
+    Ok(None)
+}
+
+// convert a Python replacement string (used by Semgrep) to the regex crate's
+// syntax. `\1` -> `${1}`, `\g<name>` -> `${name}` and `$` -> `$$`.
+fn python_replacement(replacement: &str) -> String {
+    let mut result = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => result.push_str("$$"),
+            '\\' => match chars.peek() {
+                Some(d) if d.is_ascii_digit() => {
+                    let mut group = String::new();
+                    while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                        group.push(*d);
+                        chars.next();
+                    }
+                    result.push_str(&format!("${{{}}}", group));
+                }
+                Some('g') => {
+                    chars.next();
+                    let rest: String = chars.clone().collect();
+                    match rest.strip_prefix('<').and_then(|r| r.find('>')) {
+                        Some(close) => {
+                            let name: String = rest[1..close + 1].to_string();
+                            result.push_str(&format!("${{{}}}", name));
+                            // skip `<name>`.
+                            for _ in 0..close + 2 {
+                                chars.next();
+                            }
+                        }
+                        None => result.push_str("\\g"),
+                    }
+                }
+                Some('n') => {
+                    chars.next();
+                    result.push('\n');
+                }
+                Some('t') => {
+                    chars.next();
+                    result.push('\t');
+                }
+                Some('\\') => {
+                    chars.next();
+                    result.push('\\');
+                }
+                _ => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CliOutput;
+
+    const TARGET: &str = "tests/autofix/insecure-copy.c";
+
+    #[test]
+    fn test_fixed_file_path() {
+        assert_eq!(
+            fixed_file_path(TARGET),
+            "tests/autofix/insecure-copy.fixed.c"
+        );
+        assert_eq!(fixed_file_path("a/foo.test.yaml"), "a/foo.test.fixed.yaml");
+        assert_eq!(fixed_file_path("Dockerfile"), "Dockerfile.fixed");
+    }
+
+    #[test]
+    fn test_python_replacement() {
+        assert_eq!(python_replacement(r"f(\1, \g<name>)"), "f(${1}, ${name})");
+        assert_eq!(python_replacement(r"$x\\y"), r"$$x\y");
+    }
+
+    #[test]
+    fn test_check_fixes() {
+        let output = CliOutput::from_json_file("tests/outputs/insecure-copy.json").unwrap();
+        let matches: Vec<&CliMatch> = output.results.iter().collect();
+
+        let result = check_fixes(TARGET, &matches).unwrap().unwrap();
+        assert!(result.passed(), "{}", result);
+
+        // only apply the first fix, the second line should be in the diff.
+        let result = check_fixes(TARGET, &matches[..1]).unwrap().unwrap();
+        let diff = result.diff.unwrap();
+        assert!(diff.contains("-    fgets(buf, sizeof(buf), stdin);"));
+        assert!(diff.contains("+    gets(buf);"));
+
+        // no `.fixed` file.
+        assert!(
+            check_fixes("tests/rule-tests/insecure-functions.c", &matches)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_apply_fixed_lines() {
+        let mut output = CliOutput::from_json_file("tests/outputs/insecure-copy.json").unwrap();
+        let m = &mut output.results[0];
+        m.extra.fix = None;
+        m.extra.fixed_lines = Some(vec!["    memcpy(buf, src, 16);".to_string()]);
+
+        let content = read_file_to_string(TARGET).unwrap();
+        let fixed = apply_fixes(&content, &[&output.results[0]]).unwrap();
+        assert!(fixed.contains("\n    memcpy(buf, src, 16);\n    // ruleid: use-fgets\n"));
+    }
+}
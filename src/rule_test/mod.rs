@@ -0,0 +1,3 @@
+pub(crate) mod annotations;
+pub(crate) mod autofix;
+pub(crate) mod runner;
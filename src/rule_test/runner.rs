@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use super::annotations::parse_annotations;
+use super::autofix::{check_fixes, AutofixResult};
+use crate::output::cli_output_struct::CliMatch;
+use crate::rules::semgrep_generic_rule::matches_check_id;
+use crate::utils::{find_files, read_file_to_string};
+use crate::{Args, CliOutput, GenericRuleExt, GenericRuleFile, Result};
+
+// ----- START RuleTest
+
+/// a rule file and the test targets that go with it. Similar to
+/// `semgrep --test`, the targets of `rules/foo.yaml` are the other files in
+/// `rules/` named `foo.*` (e.g., `foo.py` or `foo.test.yaml`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleTest {
+    pub rule_file: String,
+    pub targets: Vec<String>,
+}
+
+impl RuleTest {
+    /// create a new RuleTest.
+    pub fn new(rule_file: String, targets: Vec<String>) -> RuleTest {
+        RuleTest { rule_file, targets }
+    }
+
+    /// find all the rule files in paths and pair them with their test targets.
+    /// Rule files without targets are skipped. The results are sorted by the
+    /// rule file path.
+    pub fn find(paths: Vec<&str>) -> Vec<RuleTest> {
+        let mut tests: Vec<RuleTest> = Vec::new();
+        for p in paths {
+            for rule_file in find_files(p, &None, &None) {
+                let targets = find_targets(&rule_file);
+                if !targets.is_empty() {
+                    tests.push(RuleTest::new(rule_file, targets));
+                }
+            }
+        }
+        tests.sort_by(|a, b| a.rule_file.cmp(&b.rule_file));
+        tests
+    }
+
+    /// run the rules against the test targets with Semgrep and compare the
+    /// results with the annotations in the targets.
+    pub fn run(&self) -> Result<RuleTestReport> {
+        let rules = read_file_to_string(&self.rule_file)?;
+        let output = Args::default(rules, self.targets.clone()).execute()?;
+        self.evaluate(&output)
+    }
+
+    /// compare the Semgrep output with the annotations in the test targets.
+    /// If a target has a `.fixed` file (e.g., `foo.fixed.py`), the fixes in the
+    /// output are applied to the target and compared with it. Use this if you
+    /// already have the results (e.g., from a previous run).
+    pub fn evaluate(&self, output: &CliOutput) -> Result<RuleTestReport> {
+        let rule_file = GenericRuleFile::from_file(&self.rule_file)?;
+        let rule_ids: Vec<String> = rule_file
+            .rules
+            .iter()
+            .filter_map(|r| r.get_id().ok())
+            .map(|id| id.to_string())
+            .collect();
+
+        // rule ID -> lines that should (expected) and did (reported) match.
+        let mut expected: BTreeMap<&str, BTreeSet<TestLine>> = BTreeMap::new();
+        let mut reported: BTreeMap<&str, BTreeSet<TestLine>> = BTreeMap::new();
+
+        for target in &self.targets {
+            let content = read_file_to_string(target)?;
+            for annotation in parse_annotations(&content) {
+                if !annotation.kind.expects_match() {
+                    continue;
+                }
+                for id in &annotation.rule_ids {
+                    if let Some(rule_id) = rule_ids.iter().find(|r| *r == id) {
+                        expected
+                            .entry(rule_id)
+                            .or_default()
+                            .insert(TestLine::new(target, annotation.line));
+                    }
+                }
+            }
+        }
+
+        // target -> matches of the rules in the file.
+        let mut matches: BTreeMap<&str, Vec<&CliMatch>> = BTreeMap::new();
+
+        for result in &output.results {
+            let target = match self.targets.iter().find(|t| same_path(t, &result.path)) {
+                Some(t) => t,
+                None => continue,
+            };
+            if let Some(rule_id) = rule_ids
+                .iter()
+                .find(|r| matches_check_id(r, &result.check_id))
+            {
+                reported
+                    .entry(rule_id)
+                    .or_default()
+                    .insert(TestLine::new(target, result.start.line as usize));
+                matches.entry(target).or_default().push(result);
+            }
+        }
+
+        let mut fixes: Vec<AutofixResult> = Vec::new();
+        for target in &self.targets {
+            let target_matches = matches.get(target.as_str()).cloned().unwrap_or_default();
+            if let Some(fix) = check_fixes(target, &target_matches)? {
+                fixes.push(fix);
+            }
+        }
+
+        let empty: BTreeSet<TestLine> = BTreeSet::new();
+        let results = rule_ids
+            .iter()
+            .map(|id| {
+                let exp = expected.get(id.as_str()).unwrap_or(&empty);
+                let rep = reported.get(id.as_str()).unwrap_or(&empty);
+                RuleTestResult {
+                    rule_id: id.clone(),
+                    true_positives: exp.intersection(rep).cloned().collect(),
+                    false_positives: rep.difference(exp).cloned().collect(),
+                    false_negatives: exp.difference(rep).cloned().collect(),
+                }
+            })
+            .collect();
+
+        Ok(RuleTestReport {
+            rule_file: self.rule_file.clone(),
+            results,
+            fixes,
+        })
+    }
+}
+
+// ----- END RuleTest
+
+/// find and run all the rule tests in paths. See `RuleTest::find`.
+pub fn run_rule_tests(paths: Vec<&str>) -> Result<Vec<RuleTestReport>> {
+    RuleTest::find(paths).iter().map(|t| t.run()).collect()
+}
+
+// ----- START reports
+
+/// a line in a test target.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TestLine {
+    pub path: String,
+    /// starts at 1.
+    pub line: usize,
+}
+
+impl TestLine {
+    fn new(path: &str, line: usize) -> TestLine {
+        TestLine {
+            path: path.to_string(),
+            line,
+        }
+    }
+}
+
+impl fmt::Display for TestLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.path, self.line)
+    }
+}
+
+/// the test results of one rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleTestResult {
+    pub rule_id: String,
+    /// lines annotated with `ruleid` (or `todook`) that matched.
+    pub true_positives: Vec<TestLine>,
+    /// lines that matched but were not annotated with `ruleid` (or `todook`).
+    pub false_positives: Vec<TestLine>,
+    /// lines annotated with `ruleid` (or `todook`) that didn't match.
+    pub false_negatives: Vec<TestLine>,
+}
+
+impl RuleTestResult {
+    /// return true if there are no false positives or false negatives.
+    pub fn passed(&self) -> bool {
+        self.false_positives.is_empty() && self.false_negatives.is_empty()
+    }
+}
+
+/// the test results of all the rules in a rule file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleTestReport {
+    pub rule_file: String,
+    pub results: Vec<RuleTestResult>,
+    /// autofix results of the targets with a `.fixed` file.
+    pub fixes: Vec<AutofixResult>,
+}
+
+impl RuleTestReport {
+    /// return true if all the rules in the file and all the autofix checks
+    /// passed.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed()) && self.fixes.iter().all(|f| f.passed())
+    }
+}
+
+impl fmt::Display for RuleTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.rule_file)?;
+        for r in &self.results {
+            let status = if r.passed() { "PASS" } else { "FAIL" };
+            writeln!(
+                f,
+                "  {} {}: {} true positive(s), {} false positive(s), {} false negative(s)",
+                status,
+                r.rule_id,
+                r.true_positives.len(),
+                r.false_positives.len(),
+                r.false_negatives.len()
+            )?;
+            for line in &r.false_positives {
+                writeln!(f, "    false positive: {}", line)?;
+            }
+            for line in &r.false_negatives {
+                writeln!(f, "    false negative: {}", line)?;
+            }
+        }
+        for fix in &self.fixes {
+            write!(f, "  {}", fix)?;
+        }
+        Ok(())
+    }
+}
+
+// ----- END reports
+
+// return the test targets of a rule file. `dir/foo.yaml` -> `dir/foo.*`
+// except the rule file itself and the autofix files (e.g., `dir/foo.fixed.py`
+// or `dir/foo.test.fixed.yaml`).
+fn find_targets(rule_file: &str) -> Vec<String> {
+    let path = Path::new(rule_file);
+    let (dir, stem) = match (path.parent(), path.file_stem().and_then(|s| s.to_str())) {
+        (Some(d), Some(s)) => (d, s),
+        _ => return Vec::new(),
+    };
+    let prefix = format!("{}.", stem);
+
+    let entries = match std::fs::read_dir(if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    }) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut targets: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let is_target = name.starts_with(&prefix) && !is_fixed_file(&name) && e.path() != path;
+            match is_target {
+                true => Some(dir.join(name).to_string_lossy().to_string()),
+                false => None,
+            }
+        })
+        .collect();
+    targets.sort();
+    targets
+}
+
+// return true if the file name is an autofix file, i.e., `foo.fixed.ext`.
+fn is_fixed_file(name: &str) -> bool {
+    Path::new(name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().ends_with(".fixed"))
+        .unwrap_or(false)
+}
+
+// return true if both paths point to the same file.
+fn same_path(a: &str, b: &str) -> bool {
+    if Path::new(a) == Path::new(b) {
+        return true;
+    }
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULE_FILE: &str = "tests/rule-tests/insecure-functions.yaml";
+    const TARGET: &str = "tests/rule-tests/insecure-functions.c";
+
+    #[test]
+    fn test_find() {
+        let tests = RuleTest::find(vec!["tests/rule-tests"]);
+        assert_eq!(
+            tests,
+            vec![RuleTest::new(
+                RULE_FILE.to_string(),
+                vec![TARGET.to_string()]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let test = RuleTest::new(RULE_FILE.to_string(), vec![TARGET.to_string()]);
+        let output = CliOutput::from_json_file("tests/outputs/insecure-functions.json").unwrap();
+        let report = test.evaluate(&output).unwrap();
+
+        assert!(!report.passed());
+        assert_eq!(report.results.len(), 2);
+
+        let strcpy = &report.results[0];
+        assert_eq!(strcpy.rule_id, "use-of-strcpy");
+        // the todook line matches and is expected.
+        assert_eq!(
+            strcpy.true_positives,
+            vec![TestLine::new(TARGET, 6), TestLine::new(TARGET, 15)]
+        );
+        assert!(strcpy.passed());
+
+        let gets = &report.results[1];
+        assert_eq!(gets.rule_id, "use-of-gets");
+        assert_eq!(gets.true_positives, vec![TestLine::new(TARGET, 9)]);
+        assert_eq!(gets.false_positives, vec![TestLine::new(TARGET, 11)]);
+        assert_eq!(gets.false_negatives, vec![TestLine::new(TARGET, 18)]);
+        assert!(report.fixes.is_empty());
+    }
+
+    #[test]
+    fn test_evaluate_autofix() {
+        let tests = RuleTest::find(vec!["tests/autofix"]);
+        assert_eq!(tests.len(), 1);
+        assert_eq!(tests[0].targets, vec!["tests/autofix/insecure-copy.c"]);
+
+        let output = CliOutput::from_json_file("tests/outputs/insecure-copy.json").unwrap();
+        let report = tests[0].evaluate(&output).unwrap();
+        assert_eq!(report.fixes.len(), 1);
+        assert!(report.passed(), "{}", report);
+    }
+
+    // only runs if Semgrep is installed.
+    #[test]
+    fn test_run() {
+        if !crate::is_installed() {
+            return;
+        }
+        let reports = run_rule_tests(vec!["tests/rule-tests"]).unwrap();
+        assert_eq!(reports.len(), 1);
+    }
+}
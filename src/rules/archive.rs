@@ -0,0 +1,281 @@
+// Read rule and policy files from archives without unpacking them.
+//
+// Supported formats are `.tar`, `.tar.gz` (`.tgz`) and `.zip`. The format of
+// an archive file is detected from its extension and the format of an
+// in-memory archive from its first bytes. Files in the archive are selected
+// with the same logic as `find_files`: hidden files and directories are
+// skipped, only include extensions are used and files that end in exclude are
+// skipped. Files are sorted by their path in the archive, which is also the
+// file name in the rule sources and the load report.
+
+use std::fs::File;
+use std::io::{Cursor, Read, Seek};
+use std::path::{Component, Path};
+
+use flate2::read::GzDecoder;
+
+use super::load_report::{FileContent, LoadErrorKind};
+use crate::error::{Error, Result};
+use crate::utils::is_included;
+
+/// the formats that can be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// detect the format from the file name. Returns None if the extension is
+    /// not `.tar`, `.tar.gz`, `.tgz` or `.zip`.
+    pub fn from_path(path: &str) -> Option<ArchiveFormat> {
+        let path = path.to_lowercase();
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if path.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if path.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// detect the format from the first bytes of the archive.
+    pub fn from_bytes(bytes: &[u8]) -> Option<ArchiveFormat> {
+        if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Some(ArchiveFormat::Zip)
+        } else if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveFormat::TarGz)
+        } else if bytes.len() > 262 && &bytes[257..262] == b"ustar" {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+// return the selected files in an archive file, sorted by path. The format is
+// detected from the extension and then the content.
+pub(crate) fn read_archive_file(
+    path: &str,
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> Result<Vec<FileContent>> {
+    let format = match ArchiveFormat::from_path(path) {
+        Some(format) => format,
+        None => {
+            let mut magic = Vec::new();
+            File::open(path)?.take(512).read_to_end(&mut magic)?;
+            ArchiveFormat::from_bytes(&magic)
+                .ok_or_else(|| Error::new(format!("{} is not a tar or zip archive.", path)))?
+        }
+    };
+    read_archive(File::open(path)?, format, include, exclude)
+        .map_err(|e| Error::new(format!("Error reading archive {}: {}", path, e)))
+}
+
+// same as read_archive_file but for an archive in memory.
+pub(crate) fn read_archive_bytes(
+    bytes: &[u8],
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> Result<Vec<FileContent>> {
+    let format = ArchiveFormat::from_bytes(bytes)
+        .ok_or_else(|| Error::new("The bytes are not a tar or zip archive.".to_string()))?;
+    read_archive(Cursor::new(bytes), format, include, exclude)
+        .map_err(|e| Error::new(format!("Error reading archive: {}", e)))
+}
+
+fn read_archive<R: Read + Seek>(
+    reader: R,
+    format: ArchiveFormat,
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> std::result::Result<Vec<FileContent>, String> {
+    let mut entries = match format {
+        ArchiveFormat::Tar => read_tar(reader, include, exclude),
+        ArchiveFormat::TarGz => read_tar(GzDecoder::new(reader), include, exclude),
+        ArchiveFormat::Zip => read_zip(reader, include, exclude),
+    }?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+fn read_tar<R: Read>(
+    reader: R,
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> std::result::Result<Vec<FileContent>, String> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = match entry.path() {
+            Ok(path) => match entry_name(&path, include, exclude) {
+                Some(name) => name,
+                None => continue,
+            },
+            Err(e) => return Err(e.to_string()),
+        };
+        entries.push((name, read_entry(&mut entry)));
+    }
+    Ok(entries)
+}
+
+fn read_zip<R: Read + Seek>(
+    reader: R,
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> std::result::Result<Vec<FileContent>, String> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
+        if !file.is_file() {
+            continue;
+        }
+        // skip files with unsafe paths (e.g., `../rule.yaml`).
+        let name = match file
+            .enclosed_name()
+            .and_then(|path| entry_name(path, include, exclude))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        entries.push((name, read_entry(&mut file)));
+    }
+    Ok(entries)
+}
+
+// return the path of a file in the archive if it should be loaded.
+fn entry_name(
+    path: &Path,
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> Option<String> {
+    // remove `./` and skip hidden files and directories like find_files.
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => {
+                let part = part.to_string_lossy();
+                if part.starts_with('.') {
+                    return None;
+                }
+                parts.push(part.to_string());
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    let name = parts.join("/");
+    match is_included(Path::new(&name), include, exclude) {
+        true => Some(name),
+        false => None,
+    }
+}
+
+// read a file in the archive to a string.
+fn read_entry<R: Read>(reader: &mut R) -> std::result::Result<String, (LoadErrorKind, String)> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map(|_| content)
+        .map_err(|e| (LoadErrorKind::Read, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAR_GZ: &str = "tests/archive/rules.tar.gz";
+    const ZIP: &str = "tests/archive/rules.zip";
+
+    #[test]
+    fn test_read_archive() {
+        let expected = [
+            "policies/policy.yaml",
+            "rules/cpp/memcpy.yaml",
+            "rules/eval.yml",
+            "rules/invalid-utf8.yaml",
+        ];
+        for archive in [TAR_GZ, ZIP] {
+            let entries = read_archive_file(archive, &None, &None).unwrap();
+            let names: Vec<&str> = entries.iter().map(|(n, _)| n.as_str()).collect();
+            assert_eq!(names, expected, "{}", archive);
+            assert_eq!(entries[3].1.as_ref().unwrap_err().0, LoadErrorKind::Read);
+
+            // the same files from memory.
+            let bytes = std::fs::read(archive).unwrap();
+            assert_eq!(read_archive_bytes(&bytes, &None, &None).unwrap(), entries);
+
+            // include and exclude.
+            let entries = read_archive_file(archive, &Some(vec!["yml"]), &None).unwrap();
+            assert_eq!(entries.len(), 1);
+            let entries =
+                read_archive_file(archive, &Some(vec!["yml", "md"]), &Some(vec![".yml"])).unwrap();
+            assert_eq!(entries[0].0, "rules/README.md");
+        }
+    }
+
+    #[test]
+    fn test_from_archive() {
+        use crate::{GenericRuleIndex, IndexOptions, PolicyIndex};
+
+        for archive in [TAR_GZ, ZIP] {
+            let (ri, report) =
+                GenericRuleIndex::from_archive(archive, None, None, &IndexOptions::default())
+                    .unwrap();
+            let mut ids = ri.get_ids();
+            ids.sort();
+            assert_eq!(ids, ["eval-in-archive", "memcpy-insecure-use"]);
+            assert_eq!(
+                ri.get_source("eval-in-archive").unwrap().file,
+                "rules/eval.yml"
+            );
+            // the policy is not a rule file.
+            let errors: Vec<(&str, LoadErrorKind)> = report
+                .errors
+                .iter()
+                .map(|e| (e.file.as_str(), e.kind))
+                .collect();
+            assert_eq!(
+                errors,
+                [
+                    ("policies/policy.yaml", LoadErrorKind::Parse),
+                    ("rules/invalid-utf8.yaml", LoadErrorKind::Read)
+                ]
+            );
+
+            let bytes = std::fs::read(archive).unwrap();
+            let (pi, report) =
+                PolicyIndex::from_archive_bytes(&bytes, None, None, &ri, false).unwrap();
+            let policy = pi.get_policy("archive-policy").unwrap();
+            assert!(policy.get_content().contains("memcpy-insecure-use"));
+            assert!(pi.get_policy("all").is_some());
+            // the rule files are not policies.
+            assert_eq!(report.loaded, ["policies/policy.yaml"]);
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            ArchiveFormat::from_path("rules-1.2.TGZ"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(ArchiveFormat::from_path("rules.yaml"), None);
+        assert_eq!(
+            ArchiveFormat::from_bytes(&std::fs::read(ZIP).unwrap()),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::from_bytes(b"rules:"), None);
+        assert!(read_archive_bytes(b"rules:", &None, &None).is_err());
+        assert!(read_archive_file("tests/rules/multiple-rules.yaml", &None, &None).is_err());
+    }
+}
@@ -0,0 +1,413 @@
+use serde_yaml::{Mapping, Value};
+
+use super::rule::{
+    FixRegex, KeyOrder, MetavariableRegex, Mode, OneOrMany, Paths, PatternClause, PatternOperator,
+    Rule, Severity, TaintSpec,
+};
+use super::semgrep_generic_rule::{GenericRule, GenericRuleFile};
+use crate::{Error, Result};
+
+// ----- START RuleBuilder
+
+/// builds a rule in code instead of writing YAML by hand. Operators are
+/// created with the constructors on PatternOperator and TaintSpec (e.g.,
+/// `PatternOperator::pattern_not`). `build` validates the rule against the
+/// rule schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleBuilder {
+    rule: Rule,
+}
+
+impl RuleBuilder {
+    /// create a new RuleBuilder for a rule with this ID.
+    pub fn new(id: &str) -> RuleBuilder {
+        RuleBuilder {
+            rule: Rule {
+                id: id.to_string(),
+                message: None,
+                severity: None,
+                languages: None,
+                mode: None,
+                metadata: None,
+                options: None,
+                paths: None,
+                version: None,
+                pattern: None,
+                patterns: None,
+                pattern_either: None,
+                pattern_regex: None,
+                pattern_sources: None,
+                pattern_propagators: None,
+                pattern_sanitizers: None,
+                pattern_sinks: None,
+                join: None,
+                extract: None,
+                dest_language: None,
+                transform: None,
+                reduce: None,
+                fix: None,
+                fix_regex: None,
+                extra: Mapping::new(),
+                key_order: KeyOrder::default(),
+            },
+        }
+    }
+
+    /// set the rule's message.
+    pub fn message(mut self, message: &str) -> Self {
+        self.rule.message = Some(message.to_string());
+        self
+    }
+
+    /// set the rule's severity.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.rule.severity = Some(severity);
+        self
+    }
+
+    /// add a language to the rule.
+    pub fn language(mut self, language: &str) -> Self {
+        self.rule
+            .languages
+            .get_or_insert_with(Vec::new)
+            .push(language.to_string());
+        self
+    }
+
+    /// add languages to the rule.
+    pub fn languages(self, languages: Vec<&str>) -> Self {
+        languages.into_iter().fold(self, |b, l| b.language(l))
+    }
+
+    /// set the rule's mode. Taint mode is set automatically when a source or
+    /// sink is added.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.rule.mode = Some(mode);
+        self
+    }
+
+    /// add a key to the rule's metadata.
+    pub fn metadata<V: Into<Value>>(mut self, key: &str, value: V) -> Self {
+        self.rule
+            .metadata
+            .get_or_insert_with(Mapping::new)
+            .insert(Value::from(key), value.into());
+        self
+    }
+
+    /// add a key to the rule's options.
+    pub fn option<V: Into<Value>>(mut self, key: &str, value: V) -> Self {
+        self.rule
+            .options
+            .get_or_insert_with(Mapping::new)
+            .insert(Value::from(key), value.into());
+        self
+    }
+
+    /// add a path to `paths.include`.
+    pub fn include_path(mut self, path: &str) -> Self {
+        self.rule
+            .paths
+            .get_or_insert_with(Paths::default)
+            .include
+            .get_or_insert_with(Vec::new)
+            .push(path.to_string());
+        self
+    }
+
+    /// add a path to `paths.exclude`.
+    pub fn exclude_path(mut self, path: &str) -> Self {
+        self.rule
+            .paths
+            .get_or_insert_with(Paths::default)
+            .exclude
+            .get_or_insert_with(Vec::new)
+            .push(path.to_string());
+        self
+    }
+
+    /// set the rule's top-level `pattern`.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.rule.pattern = Some(pattern.to_string());
+        self
+    }
+
+    /// set the rule's top-level `pattern-regex`.
+    pub fn pattern_regex(mut self, regex: &str) -> Self {
+        self.rule.pattern_regex = Some(regex.to_string());
+        self
+    }
+
+    /// set the rule's top-level `patterns` (all must match).
+    pub fn patterns(mut self, patterns: Vec<PatternOperator>) -> Self {
+        self.rule.patterns = Some(patterns);
+        self
+    }
+
+    /// set the rule's top-level `pattern-either` (any can match).
+    pub fn pattern_either(mut self, patterns: Vec<PatternOperator>) -> Self {
+        self.rule.pattern_either = Some(patterns);
+        self
+    }
+
+    /// add a taint source.
+    pub fn source(mut self, source: TaintSpec) -> Self {
+        self.rule
+            .pattern_sources
+            .get_or_insert_with(Vec::new)
+            .push(source);
+        self.taint()
+    }
+
+    /// add a taint propagator.
+    pub fn propagator(mut self, propagator: TaintSpec) -> Self {
+        self.rule
+            .pattern_propagators
+            .get_or_insert_with(Vec::new)
+            .push(propagator);
+        self.taint()
+    }
+
+    /// add a taint sanitizer.
+    pub fn sanitizer(mut self, sanitizer: TaintSpec) -> Self {
+        self.rule
+            .pattern_sanitizers
+            .get_or_insert_with(Vec::new)
+            .push(sanitizer);
+        self.taint()
+    }
+
+    /// add a taint sink.
+    pub fn sink(mut self, sink: TaintSpec) -> Self {
+        self.rule
+            .pattern_sinks
+            .get_or_insert_with(Vec::new)
+            .push(sink);
+        self.taint()
+    }
+
+    /// set the rule's `fix`.
+    pub fn fix(mut self, fix: &str) -> Self {
+        self.rule.fix = Some(fix.to_string());
+        self
+    }
+
+    /// set the rule's `fix-regex`.
+    pub fn fix_regex(mut self, regex: &str, replacement: &str) -> Self {
+        self.rule.fix_regex = Some(FixRegex {
+            regex: regex.to_string(),
+            replacement: replacement.to_string(),
+            count: None,
+        });
+        self
+    }
+
+    /// return the rule without validating it.
+    pub fn build_unchecked(self) -> Rule {
+        self.rule
+    }
+
+    /// validate the rule against the rule schema and return it. Returns
+    /// `Error::SchemaError` if the rule is not valid.
+    pub fn build(self) -> Result<Rule> {
+        let rule_file = GenericRuleFile {
+            rules: vec![self.rule.to_generic()?],
+        };
+        let violations = rule_file.validate_schema();
+        match violations.is_empty() {
+            true => Ok(self.rule),
+            false => Err(Error::SchemaError(violations)),
+        }
+    }
+
+    /// validate the rule and return it as a GenericRule.
+    pub fn build_generic(self) -> Result<GenericRule> {
+        self.build()?.to_generic()
+    }
+
+    // switch to taint mode if the mode is not set.
+    fn taint(mut self) -> Self {
+        self.rule.mode.get_or_insert(Mode::Taint);
+        self
+    }
+}
+
+// ----- END RuleBuilder
+
+// ----- START operator constructors
+
+impl PatternOperator {
+    /// `pattern: ...`
+    pub fn pattern(pattern: &str) -> PatternOperator {
+        PatternOperator::Pattern(pattern.to_string())
+    }
+
+    /// `pattern-regex: ...`
+    pub fn pattern_regex(regex: &str) -> PatternOperator {
+        PatternOperator::PatternRegex(regex.to_string())
+    }
+
+    /// `pattern-not-regex: ...`
+    pub fn pattern_not_regex(regex: &str) -> PatternOperator {
+        PatternOperator::PatternNotRegex(regex.to_string())
+    }
+
+    /// `patterns: [...]`
+    pub fn patterns(patterns: Vec<PatternOperator>) -> PatternOperator {
+        PatternOperator::Patterns(patterns)
+    }
+
+    /// `pattern-either: [...]`
+    pub fn either(patterns: Vec<PatternOperator>) -> PatternOperator {
+        PatternOperator::PatternEither(patterns)
+    }
+
+    /// `pattern-inside: ...`
+    pub fn pattern_inside(pattern: &str) -> PatternOperator {
+        PatternOperator::PatternInside(PatternClause::Pattern(pattern.to_string()))
+    }
+
+    /// `pattern-not-inside: ...`
+    pub fn pattern_not_inside(pattern: &str) -> PatternOperator {
+        PatternOperator::PatternNotInside(PatternClause::Pattern(pattern.to_string()))
+    }
+
+    /// `pattern-not: ...`
+    pub fn pattern_not(pattern: &str) -> PatternOperator {
+        PatternOperator::PatternNot(PatternClause::Pattern(pattern.to_string()))
+    }
+
+    /// `focus-metavariable: $X`
+    pub fn focus_metavariable(metavariable: &str) -> PatternOperator {
+        PatternOperator::FocusMetavariable(OneOrMany::One(metavariable.to_string()))
+    }
+
+    /// `metavariable-regex` with `metavariable` and `regex`.
+    pub fn metavariable_regex(metavariable: &str, regex: &str) -> PatternOperator {
+        PatternOperator::MetavariableRegex(MetavariableRegex {
+            metavariable: metavariable.to_string(),
+            regex: regex.to_string(),
+            constant_propagation: None,
+        })
+    }
+}
+
+impl TaintSpec {
+    /// a source, sink, etc. with a single `pattern`.
+    pub fn pattern(pattern: &str) -> TaintSpec {
+        TaintSpec {
+            pattern: Some(pattern.to_string()),
+            pattern_regex: None,
+            patterns: None,
+            pattern_either: None,
+            extra: Mapping::new(),
+        }
+    }
+
+    /// a source, sink, etc. with `patterns`.
+    pub fn patterns(patterns: Vec<PatternOperator>) -> TaintSpec {
+        TaintSpec {
+            pattern: None,
+            pattern_regex: None,
+            patterns: Some(patterns),
+            pattern_either: None,
+            extra: Mapping::new(),
+        }
+    }
+}
+
+// ----- END operator constructors
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GenericRuleExt, GenericRuleFieldsExt, GenericRuleIndex};
+
+    #[test]
+    fn test_build_search_rule() {
+        let rule = RuleBuilder::new("banned-strcpy")
+            .message("Don't use strcpy on $SRC.")
+            .severity(Severity::Error)
+            .languages(vec!["c", "cpp"])
+            .metadata("category", "security")
+            .metadata("cwe", vec!["CWE-120"])
+            .exclude_path("tests/")
+            .patterns(vec![
+                PatternOperator::pattern_inside("void $FUNC(...) { ... }"),
+                PatternOperator::either(vec![
+                    PatternOperator::pattern("strcpy($DST, $SRC)"),
+                    PatternOperator::pattern("strcat($DST, $SRC)"),
+                ]),
+                PatternOperator::pattern_not("strcpy($DST, \"...\")"),
+                PatternOperator::metavariable_regex("$DST", "^buf"),
+            ])
+            .build_generic()
+            .unwrap();
+
+        let expected: GenericRule = serde_yaml::from_str(
+            r#"
+id: banned-strcpy
+message: Don't use strcpy on $SRC.
+severity: ERROR
+languages: [c, cpp]
+metadata:
+  category: security
+  cwe: [CWE-120]
+paths:
+  exclude: [tests/]
+patterns:
+  - pattern-inside: void $FUNC(...) { ... }
+  - pattern-either:
+      - pattern: strcpy($DST, $SRC)
+      - pattern: strcat($DST, $SRC)
+  - pattern-not: strcpy($DST, "...")
+  - metavariable-regex:
+      metavariable: $DST
+      regex: ^buf
+"#,
+        )
+        .unwrap();
+        assert_eq!(rule, expected);
+
+        // the rule can be added to an index.
+        let mut ri = GenericRuleIndex::new(false);
+        ri.insert(rule.get_id().unwrap(), rule.clone());
+        assert_eq!(ri.get_rule("banned-strcpy"), Some(rule));
+    }
+
+    #[test]
+    fn test_build_taint_rule() {
+        let rule = RuleBuilder::new("sqli")
+            .message("User input flows into a query.")
+            .severity(Severity::Warning)
+            .language("python")
+            .source(TaintSpec::pattern("request.args.get(...)"))
+            .sanitizer(TaintSpec::pattern("escape(...)"))
+            .sink(TaintSpec::patterns(vec![
+                PatternOperator::pattern("cursor.execute($Q)"),
+                PatternOperator::focus_metavariable("$Q"),
+            ]))
+            .build()
+            .unwrap();
+
+        assert_eq!(rule.mode, Some(Mode::Taint));
+        let generic = rule.to_generic().unwrap();
+        assert_eq!(generic.get_mode().unwrap(), Mode::Taint);
+        assert!(GenericRuleFile {
+            rules: vec![generic]
+        }
+        .validate_schema()
+        .is_empty());
+    }
+
+    #[test]
+    fn test_build_invalid_rule() {
+        // no patterns.
+        let result = RuleBuilder::new("invalid")
+            .message("message")
+            .severity(Severity::Info)
+            .language("go")
+            .build();
+        assert!(matches!(result, Err(Error::SchemaError(_))));
+    }
+}
@@ -0,0 +1,404 @@
+// On-disk cache of parsed rule and policy files.
+//
+// Reading and deserializing the YAML files is the slow part of creating an
+// index. The cache stores the parsed content of every file with its path,
+// modification time, size and SHA-256 hash. When loading with a cache:
+// 1. files with the same modification time and size are not read.
+// 2. other files are read and hashed. If the hash is the same, the file is
+//    not parsed again.
+// 3. the rest are parsed and the cache is updated.
+//
+// Only the parsed files are cached, the index is created from them every time
+// so the result is the same as loading without a cache (e.g., with different
+// IndexOptions). Files that cannot be read or parsed are not cached.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::time::UNIX_EPOCH;
+
+use log::error;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::generic_rule_index::{parse_rule_content, ParsedRuleFile, RuleFileWithSpans};
+use super::load_report::LoadErrorKind;
+use super::policy::{parse_policy_content, ParsedPolicy, Policy};
+use crate::error::{Error, Result};
+use crate::utils::{map_ordered, write_string_to_file};
+
+// version of the cache file layout. Cache files with a different version are
+// ignored. Bump it when `IndexCache`, `CacheEntry` or the cached values change.
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+// ----- START IndexCache
+
+/// parsed rule and policy files that can be saved to disk and reused by
+/// `GenericRuleIndex::from_paths_cached` and `PolicyIndex::from_paths_cached`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexCache {
+    version: u32,
+    rules: HashMap<String, CacheEntry<RuleFileWithSpans>>,
+    policies: HashMap<String, CacheEntry<Policy>>,
+    // files that were parsed (not taken from the cache) in the last load.
+    #[serde(skip)]
+    parsed: Vec<String>,
+}
+
+// a parsed file and what it looked like when it was parsed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CacheEntry<T> {
+    // modification time since the UNIX epoch (seconds, nanoseconds).
+    modified: (u64, u32),
+    size: u64,
+    // hex encoded SHA-256 hash of the file.
+    hash: String,
+    value: T,
+}
+
+impl IndexCache {
+    /// create an empty cache.
+    pub fn new() -> IndexCache {
+        IndexCache {
+            version: CACHE_FORMAT_VERSION,
+            rules: HashMap::new(),
+            policies: HashMap::new(),
+            parsed: Vec::new(),
+        }
+    }
+
+    /// read a cache file. Returns an empty cache if the file doesn't exist,
+    /// is corrupted or was created with a different cache format.
+    pub fn load(path: &str) -> Result<IndexCache> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(IndexCache::new()),
+            Err(e) => return Err(Error::from(e)),
+        };
+        // the cache is YAML so the rules are stored as they were parsed
+        // (e.g., non-string keys and tagged values).
+        match serde_yaml::from_str::<IndexCache>(&content) {
+            Ok(cache) if cache.version == CACHE_FORMAT_VERSION => Ok(cache),
+            Ok(_) => Ok(IndexCache::new()),
+            Err(e) => {
+                error!("Ignoring the corrupted cache file {}: {}", path, e);
+                Ok(IndexCache::new())
+            }
+        }
+    }
+
+    /// write the cache to a file.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let content = serde_yaml::to_string(self)?;
+        write_string_to_file(path, &content).map_err(Error::from)
+    }
+
+    /// return the files that were parsed instead of taken from the cache in
+    /// the last load.
+    pub fn parsed(&self) -> &[String] {
+        &self.parsed
+    }
+
+    /// remove everything from the cache.
+    pub fn clear(&mut self) {
+        self.rules.clear();
+        self.policies.clear();
+        self.parsed.clear();
+    }
+
+    // return the parsed rule files, in order. Entries for files that are not
+    // in files are removed.
+    pub(crate) fn load_rules(&mut self, files: Vec<String>) -> Vec<(String, ParsedRuleFile)> {
+        let (results, parsed) = load_files(files, &mut self.rules, parse_rule_content);
+        self.parsed = parsed;
+        results
+    }
+
+    // return the parsed policy files, in order. Entries for files that are not
+    // in files are removed.
+    pub(crate) fn load_policies(&mut self, files: Vec<String>) -> Vec<(String, ParsedPolicy)> {
+        let (results, parsed) = load_files(files, &mut self.policies, parse_policy_content);
+        self.parsed = parsed;
+        results
+    }
+}
+
+impl Default for IndexCache {
+    fn default() -> Self {
+        IndexCache::new()
+    }
+}
+
+// ----- END IndexCache
+
+// ----- START load_files
+
+// the parsed file, or why the file was skipped.
+type Parsed<T> = std::result::Result<T, (LoadErrorKind, String)>;
+
+// what happened to a file.
+enum Outcome<T> {
+    // the file has not changed.
+    Unchanged(T),
+    // the file was read, it has a new entry.
+    Updated(CacheEntry<T>, bool),
+    // the file could not be read or parsed.
+    Failed(LoadErrorKind, String),
+}
+
+// return the parsed files (from the cache or parsed again) and the files that
+// were parsed. The cache is updated.
+fn load_files<T, F>(
+    files: Vec<String>,
+    entries: &mut HashMap<String, CacheEntry<T>>,
+    parse: F,
+) -> (Vec<(String, Parsed<T>)>, Vec<String>)
+where
+    T: Clone + Send + Sync,
+    F: Fn(&str) -> Parsed<T> + Sync + Send,
+{
+    let cached: &HashMap<String, CacheEntry<T>> = entries;
+    let outcomes = map_ordered(files, |file| {
+        let outcome = load_file(&file, cached.get(&file), &parse);
+        (file, outcome)
+    });
+
+    let mut new_entries: HashMap<String, CacheEntry<T>> = HashMap::new();
+    let mut results = Vec::new();
+    let mut parsed = Vec::new();
+    for (file, outcome) in outcomes {
+        match outcome {
+            Outcome::Unchanged(value) => {
+                if let Some(entry) = entries.remove(&file) {
+                    new_entries.insert(file.clone(), entry);
+                }
+                results.push((file, Ok(value)));
+            }
+            Outcome::Updated(entry, was_parsed) => {
+                if was_parsed {
+                    parsed.push(file.clone());
+                }
+                results.push((file.clone(), Ok(entry.value.clone())));
+                new_entries.insert(file, entry);
+            }
+            Outcome::Failed(kind, message) => {
+                parsed.push(file.clone());
+                results.push((file, Err((kind, message))));
+            }
+        }
+    }
+    *entries = new_entries;
+    (results, parsed)
+}
+
+// check a file against its cache entry and parse it if it has changed.
+fn load_file<T, F>(file: &str, entry: Option<&CacheEntry<T>>, parse: &F) -> Outcome<T>
+where
+    T: Clone,
+    F: Fn(&str) -> Parsed<T>,
+{
+    let metadata = match fs::metadata(file) {
+        Ok(m) => m,
+        Err(e) => {
+            error!("Error reading file: {}", e);
+            return Outcome::Failed(LoadErrorKind::Read, e.to_string());
+        }
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| (d.as_secs(), d.subsec_nanos()))
+        .unwrap_or_default();
+    let size = metadata.len();
+
+    if let Some(entry) = entry {
+        if entry.modified == modified && entry.size == size {
+            return Outcome::Unchanged(entry.value.clone());
+        }
+    }
+
+    let content = match fs::read(file) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Error reading file: {}", e);
+            return Outcome::Failed(LoadErrorKind::Read, e.to_string());
+        }
+    };
+    let hash = file_hash(&content);
+
+    // the file was touched but the content is the same.
+    if let Some(entry) = entry {
+        if entry.hash == hash {
+            let entry = CacheEntry {
+                modified,
+                size,
+                hash,
+                value: entry.value.clone(),
+            };
+            return Outcome::Updated(entry, false);
+        }
+    }
+
+    let content = match String::from_utf8(content) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Error reading file: {}", e);
+            return Outcome::Failed(LoadErrorKind::Read, e.to_string());
+        }
+    };
+    match parse(&content) {
+        Ok(value) => Outcome::Updated(
+            CacheEntry {
+                modified,
+                size,
+                hash,
+                value,
+            },
+            true,
+        ),
+        Err((kind, message)) => Outcome::Failed(kind, message),
+    }
+}
+
+// return the hex encoded SHA-256 hash of the content.
+fn file_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// ----- END load_files
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GenericRuleIndex, IndexOptions, PolicyIndex};
+
+    const RULE: &str = "rules:
+  - id: rule-1
+    message: rule 1
+    severity: ERROR
+    languages: [python]
+    pattern: eval(...)
+";
+
+    const POLICY: &str = "name: policy-1
+rules:
+  - rule-1
+";
+
+    fn load(dir: &str, cache: &mut IndexCache) -> GenericRuleIndex {
+        let (gri, _) = GenericRuleIndex::from_paths_cached(
+            vec![dir],
+            None,
+            None,
+            &IndexOptions::default(),
+            cache,
+        )
+        .unwrap();
+        gri
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir = tempfile::Builder::new().prefix("cache").tempdir().unwrap();
+        let rules = dir.path().join("rules");
+        fs::create_dir(&rules).unwrap();
+        let rule_1 = rules.join("rule-1.yaml");
+        let rule_2 = rules.join("rule-2.yaml");
+        fs::write(&rule_1, RULE).unwrap();
+        fs::write(&rule_2, RULE.replace("rule-1", "rule-2")).unwrap();
+        let rules = rules.to_str().unwrap();
+        let cache_file = dir.path().join("cache.yaml");
+        let cache_file = cache_file.to_str().unwrap();
+
+        // a missing cache file is an empty cache.
+        let mut cache = IndexCache::load(cache_file).unwrap();
+        let gri = load(rules, &mut cache);
+        assert_eq!(gri.len(), 2);
+        assert_eq!(cache.parsed().len(), 2);
+        cache.save(cache_file).unwrap();
+
+        // nothing is parsed if the files have not changed.
+        let mut cache = IndexCache::load(cache_file).unwrap();
+        let cached = load(rules, &mut cache);
+        assert!(cache.parsed().is_empty());
+        assert_eq!(cached.get_hashes(), gri.get_hashes());
+        assert_eq!(cached.get_source("rule-2"), gri.get_source("rule-2"));
+
+        // only the changed file is parsed.
+        fs::write(&rule_2, RULE.replace("rule-1", "rule-3")).unwrap();
+        let gri = load(rules, &mut cache);
+        assert_eq!(cache.parsed(), [rule_2.to_str().unwrap()]);
+        let mut ids = gri.get_ids();
+        ids.sort();
+        assert_eq!(ids, vec!["rule-1", "rule-3"]);
+
+        // removed files are removed from the cache.
+        fs::remove_file(&rule_2).unwrap();
+        let gri = load(rules, &mut cache);
+        assert_eq!(gri.get_ids(), vec!["rule-1"]);
+        assert_eq!(cache.rules.len(), 1);
+
+        // policies use the same cache.
+        let policies = dir.path().join("policies");
+        fs::create_dir(&policies).unwrap();
+        fs::write(policies.join("policy-1.yaml"), POLICY).unwrap();
+        let policies = policies.to_str().unwrap();
+        for parsed in [1, 0] {
+            let (pi, _) =
+                PolicyIndex::from_paths_cached(vec![policies], None, None, &gri, false, &mut cache)
+                    .unwrap();
+            assert_eq!(cache.parsed().len(), parsed);
+            assert_eq!(pi.get_policy("policy-1").unwrap().get_rules(), ["rule-1"]);
+        }
+        assert_eq!(cache.rules.len(), 1);
+    }
+
+    // values that JSON cannot represent are cached as they were parsed.
+    #[test]
+    fn test_yaml_values() {
+        let dir = tempfile::Builder::new().prefix("cache").tempdir().unwrap();
+        let rules = dir.path().join("rules");
+        fs::create_dir(&rules).unwrap();
+        let rule = format!(
+            "{}    metadata:\n      1: one\n      tagged: !custom value\n",
+            RULE
+        );
+        fs::write(rules.join("rule-1.yaml"), rule).unwrap();
+        let rules = rules.to_str().unwrap();
+        let cache_file = dir.path().join("cache.yaml");
+        let cache_file = cache_file.to_str().unwrap();
+
+        let mut cache = IndexCache::new();
+        let gri = load(rules, &mut cache);
+        cache.save(cache_file).unwrap();
+
+        let mut cache = IndexCache::load(cache_file).unwrap();
+        let cached = load(rules, &mut cache);
+        assert!(cache.parsed().is_empty());
+        assert_eq!(cached.get_rule("rule-1"), gri.get_rule("rule-1"));
+        let metadata = cached.get_rule("rule-1").unwrap()["metadata"].clone();
+        assert_eq!(metadata[1], "one");
+        assert!(matches!(metadata["tagged"], serde_yaml::Value::Tagged(_)));
+    }
+
+    // a corrupted cache file or one from another version is ignored.
+    #[test]
+    fn test_load_invalid() {
+        let dir = tempfile::Builder::new().prefix("cache").tempdir().unwrap();
+        let cache_file = dir.path().join("cache.yaml");
+        let cache_file = cache_file.to_str().unwrap();
+
+        fs::write(cache_file, "not a cache").unwrap();
+        assert!(IndexCache::load(cache_file).unwrap().rules.is_empty());
+
+        let mut cache = IndexCache::new();
+        cache.version = CACHE_FORMAT_VERSION + 1;
+        load("tests/rules/cpp", &mut cache);
+        cache.save(cache_file).unwrap();
+        assert!(IndexCache::load(cache_file).unwrap().rules.is_empty());
+    }
+}
@@ -0,0 +1,471 @@
+// Structural diff between two sets of rules.
+//
+// Rules are matched by ID. Rules with the same ID are compared by their content
+// hash (so formatting changes are ignored) and modified rules are compared
+// field by field. Lists are diffed item by item so adding a `pattern-not` in
+// the middle of `patterns` is reported as one added item.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_yaml::{Mapping, Value};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+use super::generic_rule_index::GenericRuleIndex;
+use super::location::escape_pointer;
+use super::rule::Severity;
+use super::semgrep_generic_rule::{
+    GenericRule, GenericRuleExt, GenericRuleFieldsExt, GenericRuleFile,
+};
+
+// ----- START types
+
+/// the kind of a change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// a change to a field in a rule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub kind: ChangeKind,
+    /// JSON pointer to the field in the rule, e.g., `/patterns/1`.
+    pub path: String,
+    /// the old value, None if the field was added.
+    pub old: Option<Value>,
+    /// the new value, None if the field was removed.
+    pub new: Option<Value>,
+}
+
+/// the changes to a rule that is in both sets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleDiff {
+    pub rule_id: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// the differences between two sets of rules. All lists are sorted by rule ID.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RuleSetDiff {
+    /// IDs of the rules that are only in the new set.
+    pub added: Vec<String>,
+    /// IDs of the rules that are only in the old set.
+    pub removed: Vec<String>,
+    /// rules that are in both sets but have different contents.
+    pub modified: Vec<RuleDiff>,
+}
+
+// ----- END types
+
+// ----- START RuleSetDiff
+
+impl RuleSetDiff {
+    /// compare the rules in two rule files. Rules without an ID are ignored.
+    pub fn between_files(old: &GenericRuleFile, new: &GenericRuleFile) -> RuleSetDiff {
+        RuleSetDiff::between(&rules_by_id(old), &rules_by_id(new))
+    }
+
+    /// compare the rules in two indexes. Rules are matched by their key in the
+    /// index.
+    pub fn between_indexes(old: &GenericRuleIndex, new: &GenericRuleIndex) -> RuleSetDiff {
+        let sorted = |ri: &GenericRuleIndex| -> BTreeMap<String, GenericRule> {
+            ri.get_index()
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
+        };
+        RuleSetDiff::between(&sorted(old), &sorted(new))
+    }
+
+    /// return true if there are no differences.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    /// render the diff as Markdown (e.g., for merge requests).
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("## Rule changes\n\n");
+        if self.is_empty() {
+            out.push_str("No changes.\n");
+            return out;
+        }
+        out.push_str(&format!(
+            "**{} added, {} removed, {} modified**\n",
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len()
+        ));
+
+        if !self.added.is_empty() {
+            out.push_str("\n### Added\n\n");
+            for id in &self.added {
+                out.push_str(&format!("- `{}`\n", id));
+            }
+        }
+        if !self.removed.is_empty() {
+            out.push_str("\n### Removed\n\n");
+            for id in &self.removed {
+                out.push_str(&format!("- `{}`\n", id));
+            }
+        }
+        if !self.modified.is_empty() {
+            out.push_str("\n### Modified\n");
+            for rule in &self.modified {
+                out.push_str(&format!("\n#### `{}`\n\n", rule.rule_id));
+                for change in &rule.changes {
+                    out.push_str(&format!("- {}\n", change.describe(true)));
+                }
+            }
+        }
+        out
+    }
+
+    // compare two maps of rule ID -> rule.
+    fn between(
+        old: &BTreeMap<String, GenericRule>,
+        new: &BTreeMap<String, GenericRule>,
+    ) -> RuleSetDiff {
+        let mut diff = RuleSetDiff::default();
+
+        for (id, old_rule) in old {
+            match new.get(id) {
+                None => diff.removed.push(id.clone()),
+                Some(new_rule) if old_rule.content_hash() != new_rule.content_hash() => {
+                    let mut changes = Vec::new();
+                    diff_values(
+                        "",
+                        &Value::Mapping(old_rule.clone()),
+                        &Value::Mapping(new_rule.clone()),
+                        &mut changes,
+                    );
+                    diff.modified.push(RuleDiff {
+                        rule_id: id.clone(),
+                        changes,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        diff.added = new
+            .keys()
+            .filter(|id| !old.contains_key(*id))
+            .cloned()
+            .collect();
+        diff
+    }
+}
+
+impl fmt::Display for RuleSetDiff {
+    // plain text rendering.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for id in &self.added {
+            writeln!(f, "added: {}", id)?;
+        }
+        for id in &self.removed {
+            writeln!(f, "removed: {}", id)?;
+        }
+        for rule in &self.modified {
+            writeln!(f, "modified: {}", rule.rule_id)?;
+            for change in &rule.changes {
+                writeln!(f, "  {}", change.describe(false))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// ----- END RuleSetDiff
+
+impl FieldChange {
+    // return a one line description of the change. Values are wrapped in
+    // backticks for Markdown.
+    fn describe(&self, markdown: bool) -> String {
+        let quote = |v: &Option<Value>| -> String {
+            let s = v.as_ref().map(value_to_string).unwrap_or_default();
+            match markdown {
+                true => format!("`{}`", s),
+                false => s,
+            }
+        };
+        let (path, arrow) = match markdown {
+            true => (format!("`{}`", self.path), "→"),
+            false => (self.path.clone(), "->"),
+        };
+
+        match self.kind {
+            ChangeKind::Added => format!("added {}: {}", path, quote(&self.new)),
+            ChangeKind::Removed => format!("removed {}: {}", path, quote(&self.old)),
+            ChangeKind::Modified => {
+                let prefix = match (self.path.as_str(), severity_change(self)) {
+                    ("/severity", Some(true)) => "severity raised".to_string(),
+                    ("/severity", Some(false)) => "severity lowered".to_string(),
+                    _ => format!("changed {}", path),
+                };
+                format!(
+                    "{}: {} {} {}",
+                    prefix,
+                    quote(&self.old),
+                    arrow,
+                    quote(&self.new)
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.describe(false))
+    }
+}
+
+// return Some(true) if the severity was raised, Some(false) if it was lowered
+// and None if either value is not INFO, WARNING or ERROR.
+fn severity_change(change: &FieldChange) -> Option<bool> {
+    let parse = |v: &Option<Value>| -> Option<Severity> {
+        v.as_ref()
+            .and_then(|v| serde_yaml::from_value::<Severity>(v.clone()).ok())
+    };
+    // only INFO, WARNING and ERROR are ordered.
+    let ordered = |s: &Severity| s <= &Severity::Error;
+    match (parse(&change.old), parse(&change.new)) {
+        (Some(old), Some(new)) if old != new && ordered(&old) && ordered(&new) => Some(new > old),
+        _ => None,
+    }
+}
+
+// ----- START field diff
+
+// compare two values and add the changes to changes.
+fn diff_values(path: &str, old: &Value, new: &Value, changes: &mut Vec<FieldChange>) {
+    match (old, new) {
+        (Value::Mapping(o), Value::Mapping(n)) => diff_mappings(path, o, n, changes),
+        (Value::Sequence(o), Value::Sequence(n)) => diff_sequences(path, o, n, changes),
+        (o, n) if !same_value(o, n) => changes.push(FieldChange {
+            kind: ChangeKind::Modified,
+            path: path.to_string(),
+            old: Some(o.clone()),
+            new: Some(n.clone()),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_mappings(path: &str, old: &Mapping, new: &Mapping, changes: &mut Vec<FieldChange>) {
+    for (key, old_value) in old {
+        let child = format!("{}/{}", path, escape_pointer(&value_to_string(key)));
+        match new.get(key) {
+            Some(new_value) => diff_values(&child, old_value, new_value, changes),
+            None => changes.push(FieldChange {
+                kind: ChangeKind::Removed,
+                path: child,
+                old: Some(old_value.clone()),
+                new: None,
+            }),
+        }
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            changes.push(FieldChange {
+                kind: ChangeKind::Added,
+                path: format!("{}/{}", path, escape_pointer(&value_to_string(key))),
+                old: None,
+                new: Some(new_value.clone()),
+            });
+        }
+    }
+}
+
+// diff the items in two lists. Removed items use their index in the old list
+// and added items use their index in the new list.
+fn diff_sequences(path: &str, old: &[Value], new: &[Value], changes: &mut Vec<FieldChange>) {
+    let keys = |items: &[Value]| -> Vec<String> {
+        items
+            .iter()
+            .map(|v| {
+                let mut rule = Mapping::new();
+                rule.insert(Value::from("v"), v.clone());
+                rule.content_hash()
+            })
+            .collect()
+    };
+    let (old_keys, new_keys) = (keys(old), keys(new));
+
+    for op in capture_diff_slices(Algorithm::Myers, &old_keys, &new_keys) {
+        match op {
+            DiffOp::Equal { .. } => {}
+            DiffOp::Delete {
+                old_index, old_len, ..
+            } => removed_items(path, old, old_index, old_len, changes),
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => added_items(path, new, new_index, new_len, changes),
+            // items replaced one by one are compared field by field.
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } if old_len == new_len => {
+                for i in 0..old_len {
+                    diff_values(
+                        &format!("{}/{}", path, new_index + i),
+                        &old[old_index + i],
+                        &new[new_index + i],
+                        changes,
+                    );
+                }
+            }
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => {
+                removed_items(path, old, old_index, old_len, changes);
+                added_items(path, new, new_index, new_len, changes);
+            }
+        }
+    }
+}
+
+fn removed_items(
+    path: &str,
+    items: &[Value],
+    start: usize,
+    len: usize,
+    changes: &mut Vec<FieldChange>,
+) {
+    for (i, item) in items.iter().enumerate().skip(start).take(len) {
+        changes.push(FieldChange {
+            kind: ChangeKind::Removed,
+            path: format!("{}/{}", path, i),
+            old: Some(item.clone()),
+            new: None,
+        });
+    }
+}
+
+fn added_items(
+    path: &str,
+    items: &[Value],
+    start: usize,
+    len: usize,
+    changes: &mut Vec<FieldChange>,
+) {
+    for (i, item) in items.iter().enumerate().skip(start).take(len) {
+        changes.push(FieldChange {
+            kind: ChangeKind::Added,
+            path: format!("{}/{}", path, i),
+            old: None,
+            new: Some(item.clone()),
+        });
+    }
+}
+
+// ----- END field diff
+
+// compare two scalars. Trailing newlines are ignored like in the content hash.
+fn same_value(old: &Value, new: &Value) -> bool {
+    match (old, new) {
+        (Value::String(o), Value::String(n)) => {
+            o.trim_end_matches('\n') == n.trim_end_matches('\n')
+        }
+        (o, n) => o == n,
+    }
+}
+
+// return a value as a one line string. Collections are written as JSON.
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.trim_end_matches('\n').replace('\n', "\\n"),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Null => "null".to_string(),
+        v => serde_json::to_string(v).unwrap_or_default(),
+    }
+}
+
+// return the rules in a file keyed by ID.
+fn rules_by_id(rule_file: &GenericRuleFile) -> BTreeMap<String, GenericRule> {
+    rule_file
+        .rules
+        .iter()
+        .filter_map(|r| r.get_id().ok().map(|id| (id.to_string(), r.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "tests/diff/old.yaml";
+    const NEW: &str = "tests/diff/new.yaml";
+
+    fn diff() -> RuleSetDiff {
+        RuleSetDiff::between_files(
+            &GenericRuleFile::from_file(OLD).unwrap(),
+            &GenericRuleFile::from_file(NEW).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_between_files() {
+        let diff = diff();
+        assert_eq!(diff.added, vec!["new-rule"]);
+        assert_eq!(diff.removed, vec!["old-rule"]);
+        // `formatted` only has formatting changes.
+        assert_eq!(diff.modified.len(), 1);
+
+        // changes are in the order of the fields in the old rule.
+        let changed = &diff.modified[0];
+        assert_eq!(changed.rule_id, "changed");
+        let changes: Vec<(ChangeKind, &str)> = changed
+            .changes
+            .iter()
+            .map(|c| (c.kind, c.path.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (ChangeKind::Added, "/patterns/1"),
+                (ChangeKind::Added, "/languages/1"),
+                (ChangeKind::Modified, "/severity"),
+                (ChangeKind::Modified, "/metadata/confidence"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let diff = diff();
+        let text = diff.to_string();
+        assert_eq!(
+            text,
+            r#"added: new-rule
+removed: old-rule
+modified: changed
+  added /patterns/1: {"pattern-not":"strcpy($DST, \"...\")"}
+  added /languages/1: cpp
+  severity raised: WARNING -> ERROR
+  changed /metadata/confidence: LOW -> HIGH
+"#
+        );
+
+        let markdown = diff.to_markdown();
+        assert!(markdown.contains("**1 added, 1 removed, 1 modified**"));
+        assert!(markdown.contains("#### `changed`"));
+        assert!(markdown.contains("- severity raised: `WARNING` → `ERROR`"));
+    }
+
+    #[test]
+    fn test_between_indexes() {
+        let old = GenericRuleIndex::from_path_simple(OLD).unwrap();
+        let new = GenericRuleIndex::from_path_simple(NEW).unwrap();
+        assert_eq!(RuleSetDiff::between_indexes(&old, &new), diff());
+        assert!(RuleSetDiff::between_indexes(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+// Canonical formatting for rule files.
+//
+// serde_yaml's output depends on the order of the keys in the Mapping and
+// writes multi-line strings as escaped strings. This formatter writes rules
+// with a fixed key order, block scalars for multi-line strings and the
+// simplest quoting that reads back as the same string. Formatting a formatted
+// file doesn't change it so it can be used to check rule repositories.
+//
+// Comments are not preserved because serde_yaml doesn't keep them. To avoid
+// losing them, format_yaml and is_formatted return an error for files with
+// comments. Use format_yaml_discarding_comments to format them anyway.
+
+use serde_yaml::{Mapping, Value};
+use yaml_rust2::scanner::Scanner;
+
+use super::semgrep_generic_rule::GenericRuleFile;
+use crate::{Error, Result};
+
+const INDENT: usize = 2;
+
+/// the order of the keys in a rule. Keys that are not in this list are
+/// written after these in their original order.
+const RULE_KEY_ORDER: [&str; 31] = [
+    "id",
+    "message",
+    "severity",
+    "languages",
+    "metadata",
+    "mode",
+    "min-version",
+    "max-version",
+    "options",
+    "paths",
+    // search mode.
+    "pattern",
+    "pattern-regex",
+    "patterns",
+    "pattern-either",
+    "match",
+    // taint mode.
+    "pattern-sources",
+    "pattern-propagators",
+    "pattern-sanitizers",
+    "pattern-sinks",
+    "taint",
+    // join mode.
+    "join",
+    // extract mode.
+    "extract",
+    "dest-language",
+    "dest-rules",
+    "transform",
+    "reduce",
+    // secrets.
+    "validators",
+    "product",
+    // autofix.
+    "fix",
+    "fix-regex",
+    // experimental.
+    "equivalences",
+];
+
+impl GenericRuleFile {
+    /// serialize the GenericRuleFile to a YAML string with the canonical
+    /// formatting. See `format_yaml`.
+    pub fn to_formatted_string(&self) -> Result<String> {
+        format_value(&serde_yaml::to_value(self)?)
+    }
+}
+
+/// format a rule file. Rule keys are sorted (id, message, severity, languages,
+/// metadata, then the pattern operators), multi-line strings are written as
+/// block scalars and strings are only quoted when needed. Returns an error if
+/// the file has comments because they cannot be preserved.
+pub fn format_yaml(yaml: &str) -> Result<String> {
+    let value = parse_rule_file(yaml)?;
+    if has_comments(yaml) {
+        return Error::wrap_str("The file has comments, formatting would remove them.");
+    }
+    format_value(&value)
+}
+
+/// same as format_yaml but removes the comments instead of returning an
+/// error.
+pub fn format_yaml_discarding_comments(yaml: &str) -> Result<String> {
+    format_value(&parse_rule_file(yaml)?)
+}
+
+/// return true if the rule file is already formatted (e.g., for `fmt --check`).
+/// Returns an error if the file has comments, see format_yaml.
+pub fn is_formatted(yaml: &str) -> Result<bool> {
+    Ok(format_yaml(yaml)? == yaml)
+}
+
+fn parse_rule_file(yaml: &str) -> Result<Value> {
+    let value: Value = serde_yaml::from_str(yaml)?;
+    if !value.get("rules").map(|r| r.is_sequence()).unwrap_or(false) {
+        return Error::wrap_str("The file doesn't have a `rules` list.");
+    }
+    Ok(value)
+}
+
+// return true if the YAML has comments. The scanner skips comments, so scan
+// one token at a time and check the text that was consumed for it. A `#` at
+// the start of a line or after whitespace starts a comment unless it's in a
+// quoted string or the content of a block scalar.
+pub(crate) fn has_comments(yaml: &str) -> bool {
+    if !yaml.contains('#') {
+        return false;
+    }
+    let chars: Vec<char> = yaml.chars().collect();
+    let mut scanner = Scanner::new(yaml.chars());
+    while scanner.mark().index() < chars.len() {
+        let start = scanner.mark().index();
+        // the YAML was already parsed so this shouldn't happen. Assume it has
+        // comments so they are not removed.
+        if scanner.fetch_next_token().is_err() {
+            return true;
+        }
+        let end = scanner.mark().index().min(chars.len());
+        if consumed_comment(&chars, start, end) {
+            return true;
+        }
+    }
+    false
+}
+
+// return true if the text that the scanner consumed for one token has a
+// comment. The text is whitespace and comments, the token and sometimes
+// whitespace and a comment after the token.
+fn consumed_comment(chars: &[char], start: usize, end: usize) -> bool {
+    let is_comment = |i: usize| chars[i] == '#' && (i == 0 || chars[i - 1].is_whitespace());
+    let mut i = start;
+    while i < end && chars[i].is_whitespace() {
+        i += 1;
+    }
+    if i == end {
+        return false;
+    }
+    match chars[i] {
+        '#' => return true,
+        '\'' | '"' => i = closing_quote(chars, i, end) + 1,
+        // only the header of a block scalar can have a comment, the rest is
+        // the content.
+        '|' | '>' => {
+            let eol = (i..end).find(|j| chars[*j] == '\n').unwrap_or(end);
+            return (i..eol).any(is_comment);
+        }
+        _ => {}
+    }
+    // the other tokens (e.g., plain scalars) end before a comment.
+    (i..end).any(is_comment)
+}
+
+// return the index of the quote that closes the quoted string at start.
+fn closing_quote(chars: &[char], start: usize, end: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < end {
+        match chars[i] {
+            // skip escaped characters and `''` in single-quoted strings.
+            '\\' if quote == '"' => i += 1,
+            '\'' if quote == '\'' && i + 1 < end && chars[i + 1] == '\'' => i += 1,
+            c if c == quote => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+    end
+}
+
+// format a rule file and check that it reads back as the same value.
+fn format_value(value: &Value) -> Result<String> {
+    let mut out = String::new();
+    match value {
+        Value::Mapping(map) => write_mapping(&mut out, &sort_rule_file(map), 0, false)?,
+        _ => return Error::wrap_str("A rule file must be a YAML mapping."),
+    }
+
+    let parsed: Value = serde_yaml::from_str(&out)?;
+    if &parsed != value {
+        return Error::wrap_str("The formatted YAML is not the same as the original.");
+    }
+    Ok(out)
+}
+
+// ----- START key order
+
+// put `rules` first and sort the keys in each rule.
+fn sort_rule_file(map: &Mapping) -> Mapping {
+    let mut sorted = Mapping::new();
+    if let Some(rules) = map.get("rules") {
+        let rules = match rules {
+            Value::Sequence(items) => Value::Sequence(
+                items
+                    .iter()
+                    .map(|r| match r {
+                        Value::Mapping(m) => Value::Mapping(sort_rule(m)),
+                        v => v.clone(),
+                    })
+                    .collect(),
+            ),
+            v => v.clone(),
+        };
+        sorted.insert(Value::from("rules"), rules);
+    }
+    for (k, v) in map {
+        if k.as_str() != Some("rules") {
+            sorted.insert(k.clone(), v.clone());
+        }
+    }
+    sorted
+}
+
+fn sort_rule(rule: &Mapping) -> Mapping {
+    let mut sorted = Mapping::new();
+    for key in RULE_KEY_ORDER {
+        if let Some(v) = rule.get(key) {
+            sorted.insert(Value::from(key), v.clone());
+        }
+    }
+    for (k, v) in rule {
+        if !sorted.contains_key(k) {
+            sorted.insert(k.clone(), v.clone());
+        }
+    }
+    sorted
+}
+
+// ----- END key order
+
+// ----- START writer
+
+// write a mapping where each key starts at `indent`. If `inline_first` is true,
+// the first key is written on the current line (e.g., after `- `).
+fn write_mapping(out: &mut String, map: &Mapping, indent: usize, inline_first: bool) -> Result<()> {
+    for (i, (key, value)) in map.iter().enumerate() {
+        if i > 0 || !inline_first {
+            push_indent(out, indent);
+        }
+        out.push_str(&scalar_to_yaml(key, indent)?);
+        out.push(':');
+        write_node(out, value, indent)?;
+    }
+    Ok(())
+}
+
+// write a sequence where each `-` starts at `indent`.
+fn write_sequence(out: &mut String, items: &[Value], indent: usize) -> Result<()> {
+    for item in items {
+        push_indent(out, indent);
+        out.push('-');
+        match item {
+            Value::Mapping(m) if !m.is_empty() => {
+                out.push(' ');
+                write_mapping(out, m, indent + INDENT, true)?;
+            }
+            _ => write_node(out, item, indent)?,
+        }
+    }
+    Ok(())
+}
+
+// write a value after `key:` or `-`. Nested collections start on a new line
+// and are indented, everything else is written after a space.
+fn write_node(out: &mut String, value: &Value, indent: usize) -> Result<()> {
+    match value {
+        Value::Mapping(m) if !m.is_empty() => {
+            out.push('\n');
+            write_mapping(out, m, indent + INDENT, false)
+        }
+        Value::Sequence(s) if !s.is_empty() => {
+            out.push('\n');
+            write_sequence(out, s, indent + INDENT)
+        }
+        Value::Mapping(_) => {
+            out.push_str(" {}\n");
+            Ok(())
+        }
+        Value::Sequence(_) => {
+            out.push_str(" []\n");
+            Ok(())
+        }
+        v => {
+            out.push(' ');
+            out.push_str(&scalar_to_yaml(v, indent)?);
+            out.push('\n');
+            Ok(())
+        }
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    out.push_str(&" ".repeat(indent));
+}
+
+// ----- END writer
+
+// ----- START scalars
+
+// return the YAML representation of a scalar. Multi-line strings are written
+// as block scalars with their lines indented under `indent`.
+fn scalar_to_yaml(value: &Value, indent: usize) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(string_to_yaml(s, indent)),
+        Value::Null => Ok("null".to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Tagged(_) => Error::wrap_str("Tagged YAML values are not supported."),
+        _ => Error::wrap_str("Collections cannot be used as keys."),
+    }
+}
+
+fn string_to_yaml(s: &str, indent: usize) -> String {
+    if s.contains('\n') {
+        if let Some(block) = block_scalar(s, indent) {
+            return block;
+        }
+        return double_quoted(s);
+    }
+    if reads_as_string(s, s) {
+        return s.to_string();
+    }
+    let single = format!("'{}'", s.replace('\'', "''"));
+    if reads_as_string(&single, s) {
+        return single;
+    }
+    double_quoted(s)
+}
+
+// return a literal block scalar (`|`) or None if the string cannot be written
+// as one (e.g., the first line starts with a space or it has control
+// characters).
+fn block_scalar(s: &str, indent: usize) -> Option<String> {
+    if s.starts_with(' ')
+        || s.starts_with('\n')
+        || s.chars().any(|c| c.is_control() && c != '\n' && c != '\t')
+    {
+        return None;
+    }
+
+    // the chomping indicator keeps the trailing newlines.
+    let content = s.trim_end_matches('\n');
+    let chomping = match s.len() - content.len() {
+        0 => "-",
+        1 => "",
+        _ => "+",
+    };
+
+    let mut block = format!("|{}", chomping);
+    let line_indent = " ".repeat(indent + INDENT);
+    for line in s.strip_suffix('\n').unwrap_or(s).split('\n') {
+        block.push('\n');
+        if !line.is_empty() {
+            block.push_str(&line_indent);
+            block.push_str(line);
+        }
+    }
+    Some(block)
+}
+
+fn double_quoted(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| format!("{:?}", s))
+}
+
+// return true if `yaml` is read back as the string `s` when used as a value.
+fn reads_as_string(yaml: &str, s: &str) -> bool {
+    if yaml.is_empty() || yaml.trim() != yaml || yaml.starts_with(['#', '-', '?', ':']) {
+        return false;
+    }
+    // the candidate must work as both a key and a value.
+    let doc = format!("{}: {}\n", yaml, yaml);
+    match serde_yaml::from_str::<Mapping>(&doc) {
+        Ok(m) => m.len() == 1 && m.get(s) == Some(&Value::String(s.to_string())),
+        Err(_) => false,
+    }
+}
+
+// ----- END scalars
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_yaml() {
+        let yaml = r#"
+rules:
+- patterns:
+    - pattern: "foo($X)"
+    - pattern-not: "foo(\"...\")\n"
+    - pattern-inside: "void $F() {\n  ...\n}\n"
+  languages: [c]
+  severity: WARNING
+  metadata: {category: security, 'cwe': ["CWE-1: x"]}
+  message: 'Calling foo with $X'
+  id: foo
+  fix: "bar()"
+"#;
+        let expected = r#"rules:
+  - id: foo
+    message: Calling foo with $X
+    severity: WARNING
+    languages:
+      - c
+    metadata:
+      category: security
+      cwe:
+        - 'CWE-1: x'
+    patterns:
+      - pattern: foo($X)
+      - pattern-not: |
+          foo("...")
+      - pattern-inside: |
+          void $F() {
+            ...
+          }
+    fix: bar()
+"#;
+        let formatted = format_yaml(yaml).unwrap();
+        assert_eq!(formatted, expected);
+        assert!(is_formatted(&formatted).unwrap());
+        assert!(!is_formatted(yaml).unwrap());
+    }
+
+    #[test]
+    fn test_idempotent() {
+        for file in [
+            "tests/rules/multiple-rules.yaml",
+            "tests/lint/lint-problems.yaml",
+            "tests/autofix/insecure-copy.yaml",
+        ] {
+            let rule_file = GenericRuleFile::from_file(file).unwrap();
+            let formatted = rule_file.to_formatted_string().unwrap();
+            assert_eq!(format_yaml(&formatted).unwrap(), formatted, "{}", file);
+
+            let again = GenericRuleFile::from_yaml(&formatted).unwrap();
+            assert_eq!(again.rules, rule_file.rules, "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_quoting() {
+        for s in [
+            "plain",
+            "",
+            "true",
+            "123",
+            "null",
+            "- item",
+            "a: b",
+            "it's",
+            "# not a comment",
+            "\"...\"",
+            " leading space",
+            "tab\there",
+            "no newline\nat the end",
+            "two newlines\n\n",
+            " indented\nblock\n",
+        ] {
+            let mut map = Mapping::new();
+            map.insert(Value::from("key"), Value::from(s));
+            let mut out = String::new();
+            write_mapping(&mut out, &map, 0, false).unwrap();
+            let parsed: Mapping = serde_yaml::from_str(&out).unwrap();
+            assert_eq!(parsed.get("key"), Some(&Value::from(s)), "{}", out);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        // tests/rules/cpp/arrays-passed-to-functions.yaml has comments.
+        let yaml =
+            std::fs::read_to_string("tests/rules/cpp/arrays-passed-to-functions.yaml").unwrap();
+        assert!(format_yaml(&yaml).is_err());
+        assert!(is_formatted(&yaml).is_err());
+        let formatted = format_yaml_discarding_comments(&yaml).unwrap();
+        assert!(!formatted.contains("# "));
+        assert!(is_formatted(&formatted).unwrap());
+
+        for (yaml, comments) in [
+            ("rules: [] # comment", true),
+            ("# comment\nrules: []", true),
+            ("rules:\n  - id: a\n    # comment\n    message: m", true),
+            ("rules:\n  - id: a#b\n    message: 'x # y'", false),
+            (
+                "rules:\n  - id: a\n    message: |\n      # not a comment\n",
+                false,
+            ),
+            ("rules:\n  - id: a\n    message: \"x\\\n      # y\"", false),
+            (
+                "rules:\n  - id: a\n    message: | # comment\n      text\n",
+                true,
+            ),
+            (
+                "rules:\n  - id: a\n    message: |\n      text\n  # comment\n",
+                true,
+            ),
+            ("rules:\n  - id: a\n    message: 'it''s # y' # z", true),
+            ("rules:\n  - id: a\n    message: 'it''s # y'", false),
+            ("rules:\n  - id: a\n    message: \"\\\" # y\"", false),
+            ("rules: [\n  # comment\n  {id: a}]", true),
+        ] {
+            assert_eq!(has_comments(yaml), comments, "{}", yaml);
+        }
+    }
+
+    #[test]
+    fn test_not_a_rule_file() {
+        assert!(format_yaml("foo: bar").is_err());
+    }
+}
@@ -1,16 +1,116 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-use super::semgrep_generic_rule::{GenericRule, GenericRuleFile};
+use serde_yaml::Value;
+
+#[cfg(feature = "archive")]
+use super::archive::{read_archive_bytes, read_archive_file};
+use super::cache::IndexCache;
+#[cfg(feature = "git")]
+use super::git::read_git_files;
+#[cfg(any(feature = "archive", feature = "git"))]
+use super::load_report::FileContent;
+use super::load_report::{LoadErrorKind, LoadReport};
+use super::location::{Position, RuleSource, YamlLocations};
+use super::semgrep_generic_rule::{
+    index_key, matches_check_id, qualified_rule_id, GenericRule, GenericRuleExt,
+    GenericRuleFieldsExt, GenericRuleFile,
+};
+use super::template::{expand_template, DEFAULT_MAX_TEMPLATE_RULES};
 use crate::error::{Error, Result};
-use crate::utils::{find_files, read_file_to_string};
+use crate::utils::{find_files_in_paths, map_ordered, read_file_to_string};
 
 use log::error;
 
+// ----- START IndexOptions
+
+/// options for creating a GenericRuleIndex from files.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexOptions {
+    /// use the fully qualified IDs that Semgrep reports (e.g.,
+    /// `cpp.security.buffer-overflow.buffer-overflow`) as keys instead of
+    /// the rule IDs. See `qualified_rule_id`.
+    pub complete: bool,
+    /// the qualified IDs are relative to this path (e.g., the root of the
+    /// rule registry). If None, the paths of the rule files are used as-is.
+    pub root: Option<String>,
+    /// what to do when more than one rule has the same ID.
+    pub duplicates: DuplicateStrategy,
+    /// return an error (`Error::LoadError`) if any file or rule is skipped
+    /// instead of skipping it.
+    pub strict: bool,
+    /// the maximum number of rules a template can expand to. Templates with
+    /// more combinations of values are skipped. If None, 1000.
+    pub max_template_rules: Option<usize>,
+}
+
+/// what to do when a rule has the same ID (key in the index) as a rule that
+/// was already added. Files are read in the order of the paths and sorted in
+/// each path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateStrategy {
+    /// return an `Error::DuplicateRule` for the first duplicate.
+    Fail,
+    /// keep the rule that was read first.
+    KeepFirst,
+    /// replace the rule with the one that was read last. This is the default.
+    #[default]
+    KeepLast,
+    /// keep both and rename the rule that was read last. The new ID is the
+    /// qualified ID of the rule (see `qualified_rule_id`), e.g., `dir.file.id`.
+    /// If that's taken too (or the index is complete), `-2`, `-3`, etc. is
+    /// appended. The rule's `id` field is updated.
+    Rename,
+}
+
+/// a rule ID that was found more than once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateRule {
+    /// key of the rule in the index.
+    pub rule_id: String,
+    /// the rule that was read first.
+    pub first: RuleSource,
+    /// the rule that was read later.
+    pub second: RuleSource,
+    /// the new ID of the second rule with `DuplicateStrategy::Rename`.
+    pub renamed_to: Option<String>,
+}
+
+impl fmt::Display for DuplicateRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` is defined in {}:{} and {}:{}",
+            self.rule_id,
+            self.first.file,
+            self.first.start_line,
+            self.second.file,
+            self.second.start_line
+        )?;
+        if let Some(new_id) = &self.renamed_to {
+            write!(f, ", renamed the second one to `{}`", new_id)?;
+        }
+        Ok(())
+    }
+}
+
+// ----- END IndexOptions
+
 // ----- START GenericRuleIndex
 
 #[allow(dead_code)]
 pub struct GenericRuleIndex {
     index: HashMap<String, GenericRule>,
+    // where each rule in the index came from, same keys as index.
+    sources: HashMap<String, RuleSource>,
+    // the positions of the nodes inside each rule that was read from a file,
+    // same keys as index. Captured when the file is parsed so they match the
+    // content that was loaded (e.g., a git revision or an archive).
+    locations: HashMap<String, YamlLocations>,
+    // the rule ID from the file for each key in the index. The key and the ID
+    // are the same if complete is false.
+    ids: HashMap<String, String>,
+    // rule IDs that were found more than once while reading the files.
+    duplicates: Vec<DuplicateRule>,
     complete: bool,
 }
 
@@ -18,6 +118,10 @@ impl GenericRuleIndex {
     pub fn new(complete: bool) -> GenericRuleIndex {
         GenericRuleIndex {
             index: HashMap::new(),
+            sources: HashMap::new(),
+            locations: HashMap::new(),
+            ids: HashMap::new(),
+            duplicates: Vec::new(),
             complete,
         }
     }
@@ -61,14 +165,96 @@ impl GenericRuleIndex {
         // };
         // Ok(gri)
 
-        generic_rule_index_from_paths(paths, include, exclude, complete).map(|index| {
-            let mut gri = GenericRuleIndex::new(complete);
-            gri.index = index;
-            gri
-        })
+        let options = IndexOptions {
+            complete,
+            ..Default::default()
+        };
+        generic_rule_index_from_paths(paths, include, exclude, &options).map(|(gri, _)| gri)
         // .map_err(|e| Error::new(e.to_string()))
     }
 
+    // create and return a new GenericRuleIndex from the files in paths with
+    // the given options.
+    pub fn from_paths_with_options(
+        paths: Vec<&str>,
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        options: &IndexOptions,
+    ) -> Result<GenericRuleIndex> {
+        GenericRuleIndex::from_paths_with_report(paths, include, exclude, options)
+            .map(|(gri, _)| gri)
+    }
+
+    // same as from_paths_with_options but also returns a report of the files
+    // that were loaded and the files and rules that were skipped.
+    pub fn from_paths_with_report(
+        paths: Vec<&str>,
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        options: &IndexOptions,
+    ) -> Result<(GenericRuleIndex, LoadReport)> {
+        generic_rule_index_from_paths(paths, include, exclude, options)
+    }
+
+    // same as from_paths_with_report but only parses the files that are not
+    // in the cache or have changed since they were cached. The cache is
+    // updated, use `IndexCache::save` to write it to disk.
+    pub fn from_paths_cached(
+        paths: Vec<&str>,
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        options: &IndexOptions,
+        cache: &mut IndexCache,
+    ) -> Result<(GenericRuleIndex, LoadReport)> {
+        let rule_files = find_files_in_paths(paths, &include, &exclude);
+        generic_rule_index_from_parsed(cache.load_rules(rule_files), options)
+    }
+
+    // create and return a new GenericRuleIndex from the rule files in a
+    // `.tar`, `.tar.gz` or `.zip` archive without unpacking it. Files are
+    // selected with include and exclude like `find_files`.
+    #[cfg(feature = "archive")]
+    pub fn from_archive(
+        path: &str,
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        options: &IndexOptions,
+    ) -> Result<(GenericRuleIndex, LoadReport)> {
+        let entries = read_archive_file(path, &include, &exclude)?;
+        generic_rule_index_from_parsed(parse_file_contents(entries), options)
+    }
+
+    // same as from_archive but for an archive in memory. The format is
+    // detected from the content.
+    #[cfg(feature = "archive")]
+    pub fn from_archive_bytes(
+        bytes: &[u8],
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        options: &IndexOptions,
+    ) -> Result<(GenericRuleIndex, LoadReport)> {
+        let entries = read_archive_bytes(bytes, &include, &exclude)?;
+        generic_rule_index_from_parsed(parse_file_contents(entries), options)
+    }
+
+    // create and return a new GenericRuleIndex from the rule files in a local
+    // git repository at revision (commit, tag or branch) without checking it
+    // out. paths are relative to the root of the repository, if empty, the
+    // whole repository is used. File names (and qualified IDs) are the same as
+    // loading the files from a checkout of the revision in repo.
+    #[cfg(feature = "git")]
+    pub fn from_git(
+        repo: &str,
+        revision: &str,
+        paths: Vec<&str>,
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        options: &IndexOptions,
+    ) -> Result<(GenericRuleIndex, LoadReport)> {
+        let files = read_git_files(repo, revision, &paths, &include, &exclude)?;
+        generic_rule_index_from_parsed(parse_file_contents(files), options)
+    }
+
     // create and return a new GenericRuleIndex from the files in one path.
     pub fn from_path(
         path: &str,
@@ -100,6 +286,106 @@ impl GenericRuleIndex {
         GenericRuleFile { rules }
     }
 
+    // add a rule to the index. If the index already had a rule with this ID,
+    // the old rule is replaced and returned. The rule doesn't have a source.
+    pub fn insert(&mut self, rule_id: &str, rule: GenericRule) -> Option<GenericRule> {
+        self.sources.remove(rule_id);
+        self.locations.remove(rule_id);
+        let id = rule.get_id().unwrap_or(rule_id).to_string();
+        self.ids.insert(rule_id.to_string(), id);
+        self.index.insert(rule_id.to_string(), rule)
+    }
+
+    // returns the rule IDs that were found more than once while reading the
+    // files and what happened to them. See DuplicateStrategy.
+    pub fn get_duplicates(&self) -> &[DuplicateRule] {
+        &self.duplicates
+    }
+
+    // returns the rule ID from the file (e.g., `buffer-overflow`) for a key in
+    // the index (e.g., `cpp.security.buffer-overflow.buffer-overflow`).
+    pub fn get_short_id(&self, key: &str) -> Option<&str> {
+        self.ids.get(key).map(|id| id.as_str())
+    }
+
+    // returns the keys in the index for a rule ID from a file. There can be
+    // more than one if the index is complete and multiple files have rules
+    // with the same ID. The keys are sorted.
+    pub fn get_qualified_ids(&self, rule_id: &str) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .ids
+            .iter()
+            .filter(|(_, id)| id.as_str() == rule_id)
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    // returns the key in the index for the `check_id` that Semgrep reports in
+    // results and errors. The check_id is either a key or ends with `.key`
+    // (e.g., the rules were passed to Semgrep in a temporary file). If more
+    // than one key matches, the longest one is used.
+    pub fn resolve_check_id(&self, check_id: &str) -> Option<&str> {
+        if let Some((key, _)) = self.index.get_key_value(check_id) {
+            return Some(key.as_str());
+        }
+        self.index
+            .keys()
+            .filter(|key| matches_check_id(key, check_id))
+            .max_by_key(|key| key.len())
+            .map(|key| key.as_str())
+    }
+
+    // returns the rule for a `check_id` reported by Semgrep. See
+    // resolve_check_id.
+    pub fn get_rule_by_check_id(&self, check_id: &str) -> Option<GenericRule> {
+        self.resolve_check_id(check_id)
+            .and_then(|key| self.get_rule(key))
+    }
+
+    // returns the content hash of a rule in the index. See
+    // GenericRuleExt::content_hash.
+    pub fn get_hash(&self, rule_id: &str) -> Option<String> {
+        self.index.get(rule_id).map(|r| r.content_hash())
+    }
+
+    // returns the content hashes of all the rules in the index. Key: rule ID,
+    // value: hash.
+    pub fn get_hashes(&self) -> HashMap<String, String> {
+        self.index
+            .iter()
+            .map(|(id, rule)| (id.clone(), rule.content_hash()))
+            .collect()
+    }
+
+    // returns where the rule came from (file and lines) if it was read from a
+    // file, otherwise, returns None.
+    pub fn get_source(&self, rule_id: &str) -> Option<&RuleSource> {
+        self.sources.get(rule_id)
+    }
+
+    // returns the position of a node in a rule read from a file. pointer is
+    // relative to the rule (e.g., `/languages/1`). If the node doesn't exist,
+    // returns the position of the closest parent.
+    pub(crate) fn get_position(&self, rule_id: &str, pointer: &str) -> Option<Position> {
+        self.locations.get(rule_id).and_then(|l| l.get(pointer))
+    }
+
+    // same as get_source but also accepts the `check_id` that Semgrep reports
+    // in results and errors (e.g., `tmp.tmpabc123.rule-id`).
+    pub fn find_source(&self, check_id: &str) -> Option<&RuleSource> {
+        if let Some(source) = self.sources.get(check_id) {
+            return Some(source);
+        }
+        // use the longest match, `a.b` is a better match than `b`.
+        self.sources
+            .iter()
+            .filter(|(id, _)| matches_check_id(id, check_id))
+            .max_by_key(|(id, _)| id.len())
+            .map(|(_, source)| source)
+    }
+
     // returns a rule if it exists in the index, otherwise, returns None.
     pub fn get_rule(&self, rule_id: &str) -> Option<GenericRule> {
         self.index.get(rule_id).cloned()
@@ -118,67 +404,565 @@ impl GenericRuleIndex {
 // return an index of rules where the key is the rule ID and the value is the
 // rule.
 //
-// If `complete` it true, this function uses the same ID that Semgrep uses which
-// contains the path followed by the rule ID in the file. E.g., if the
-// /rules/cpp/security/buffer-overflow.cpp file contains the rule with ID
+// If `options.complete` it true, this function uses the same ID that Semgrep
+// uses which contains the path followed by the rule ID in the file. E.g., if
+// the rules/cpp/security/buffer-overflow.yaml file contains the rule with ID
 // buffer-overflow, the complete ruleID will be
-// rules.cpp/security.buffer-overflow.buffer-overflow. Hence, rule ID is very
-// much dependent on the path of the registry passed to the server.
+// rules.cpp.security.buffer-overflow.buffer-overflow. Hence, rule ID is very
+// much dependent on the path of the registry passed to the server, use
+// `options.root` to make it relative to the registry.
 //
 // If `complete` is false, just the rule ID from the file will be used.
 fn generic_rule_index_from_paths(
     paths: Vec<&str>,
     include: Option<Vec<&str>>,
     exclude: Option<Vec<&str>>,
-    complete: bool,
-) -> Result<HashMap<String, GenericRule>> {
+    options: &IndexOptions,
+) -> Result<(GenericRuleIndex, LoadReport)> {
     // check the path.
     // TODO is this needed? Supposedly we will check the path before calling this function.
     // utils::check_path(&path)?;
 
-    let mut rule_files: Vec<String> = Vec::new();
-
-    for p in paths {
-        rule_files.extend(find_files(p, &include, &exclude));
-    }
-    generic_rule_index_from_files(rule_files, complete)
+    let rule_files = find_files_in_paths(paths, &include, &exclude);
+    generic_rule_index_from_files(rule_files, options)
 }
 
 fn generic_rule_index_from_files(
     rule_files: Vec<String>,
-    complete: bool,
-) -> Result<HashMap<String, GenericRule>> {
-    let mut rule_index: HashMap<String, GenericRule> = HashMap::new();
-
-    for r in rule_files {
-        let content = match read_file_to_string(&r) {
-            Ok(cn) => cn,
-            Err(e) => {
-                // TODO: need better error logging
-                error!("Error reading file: {}", e.to_string());
+    options: &IndexOptions,
+) -> Result<(GenericRuleIndex, LoadReport)> {
+    // read and parse the files (in parallel with the `parallel` feature), then
+    // merge them into the index in order.
+    let parsed = map_ordered(rule_files, parse_rule_file);
+    generic_rule_index_from_parsed(parsed, options)
+}
+
+// merge the parsed rule files into a new index in order.
+fn generic_rule_index_from_parsed(
+    parsed: Vec<(String, ParsedRuleFile)>,
+    options: &IndexOptions,
+) -> Result<(GenericRuleIndex, LoadReport)> {
+    let mut gri = GenericRuleIndex::new(options.complete);
+    let mut report = LoadReport::default();
+
+    for (r, result) in parsed {
+        let (rule_file, spans, locations) = match result {
+            Ok(parsed) => parsed,
+            Err((kind, message)) => {
+                report.push(&r, kind, message);
                 continue;
             }
         };
+        report.loaded.push(r.clone());
 
-        // create a rule file from the string
-        let rule_file = match GenericRuleFile::from_yaml(&content) {
-            Ok(rf) => rf,
-            Err(e) => {
-                // TODO: need better error logging
-                error!("Error deserializing file: {}", e.to_string());
-                continue;
+        // merge the rules into the main index. Templates are expanded and
+        // all their rules point back at the template.
+        let max_rules = options
+            .max_template_rules
+            .unwrap_or(DEFAULT_MAX_TEMPLATE_RULES);
+        for (i, template) in rule_file.rules.into_iter().enumerate() {
+            let rules = match expand_template(&template, max_rules) {
+                Ok(rules) => rules,
+                Err(e) => {
+                    error!("Error expanding template in {}: {}", r, e);
+                    report.push(&r, LoadErrorKind::Template, e.to_string());
+                    continue;
+                }
+            };
+            for rule in rules {
+                if let Err(e) = rule.get_id() {
+                    report.push(&r, LoadErrorKind::MissingId, format!("rule {}: {}", i, e));
+                }
+                let key = match index_key(&rule, &r, options.root.as_deref(), options.complete) {
+                    Some(k) => k,
+                    None => continue,
+                };
+                let (start_line, end_line) = spans.get(i).copied().unwrap_or_default();
+                let source = RuleSource {
+                    file: r.clone(),
+                    index: i,
+                    start_line,
+                    end_line,
+                };
+                let rule_locations = locations.get(i).cloned().unwrap_or_default();
+                merge_rule(&mut gri, key, rule, source, rule_locations, options);
+            }
+        }
+    }
+
+    if options.strict && !report.is_ok() {
+        return Err(Error::LoadError(report));
+    }
+    if options.duplicates == DuplicateStrategy::Fail {
+        if let Some(duplicate) = gri.duplicates.first() {
+            return Err(Error::DuplicateRule {
+                id: duplicate.rule_id.clone(),
+                first: duplicate.first.clone(),
+                second: duplicate.second.clone(),
+            });
+        }
+    }
+    if gri.index.keys().len() == 0 {
+        return Error::wrap_str("Rule index is empty.");
+    }
+    Ok((gri, report))
+}
+
+// the rules in a file, the lines of each rule and the positions of the nodes
+// in each rule.
+pub(crate) type RuleFileWithSpans = (GenericRuleFile, Vec<(usize, usize)>, Vec<YamlLocations>);
+
+// the parsed rule file, or why the file was skipped.
+pub(crate) type ParsedRuleFile = std::result::Result<RuleFileWithSpans, (LoadErrorKind, String)>;
+
+// read and deserialize a rule file.
+fn parse_rule_file(file: String) -> (String, ParsedRuleFile) {
+    let content = match read_file_to_string(&file) {
+        Ok(cn) => cn,
+        Err(e) => {
+            error!("Error reading file: {}", e);
+            return (file, Err((LoadErrorKind::Read, e.to_string())));
+        }
+    };
+    let parsed = parse_rule_content(&content);
+    (file, parsed)
+}
+
+// deserialize the rule files that were read from git or an archive.
+#[cfg(any(feature = "archive", feature = "git"))]
+fn parse_file_contents(files: Vec<FileContent>) -> Vec<(String, ParsedRuleFile)> {
+    map_ordered(files, |(file, content)| {
+        let parsed = content.and_then(|c| parse_rule_content(&c));
+        (file, parsed)
+    })
+}
+
+// deserialize the content of a rule file.
+pub(crate) fn parse_rule_content(content: &str) -> ParsedRuleFile {
+    // create a rule file from the string
+    let rule_file = match GenericRuleFile::from_yaml(content) {
+        Ok(rf) => rf,
+        Err(e) => {
+            error!("Error deserializing file: {}", e);
+            return Err((LoadErrorKind::Parse, e.to_string()));
+        }
+    };
+
+    // find the lines of each rule in the file and the positions inside them.
+    let locations = YamlLocations::from_yaml(content);
+    let spans = locations.rule_spans(content);
+    Ok((rule_file, spans, locations.rule_locations()))
+}
+
+// add a rule read from a file to the index. Rules with an ID that is already
+// in the index are handled according to options.duplicates.
+fn merge_rule(
+    gri: &mut GenericRuleIndex,
+    key: String,
+    mut rule: GenericRule,
+    source: RuleSource,
+    locations: YamlLocations,
+    options: &IndexOptions,
+) {
+    let id = rule.get_id().unwrap_or_default().to_string();
+    let first = match gri.sources.get(&key) {
+        Some(first) => first.clone(),
+        None => {
+            add_rule(gri, key, id, rule, source, locations);
+            return;
+        }
+    };
+
+    let mut duplicate = DuplicateRule {
+        rule_id: key.clone(),
+        first,
+        second: source.clone(),
+        renamed_to: None,
+    };
+    match options.duplicates {
+        DuplicateStrategy::Fail | DuplicateStrategy::KeepFirst => {}
+        DuplicateStrategy::KeepLast => add_rule(gri, key, id, rule, source, locations),
+        DuplicateStrategy::Rename => {
+            let root = options.root.as_deref();
+            // complete keys already have the file prefix.
+            let base = match options.complete {
+                true => id.clone(),
+                false => qualified_rule_id(&source.file, root, &id),
+            };
+            let new_key = |new_id: &str| match options.complete {
+                true => qualified_rule_id(&source.file, root, new_id),
+                false => new_id.to_string(),
+            };
+            let mut new_id = base.clone();
+            let mut n = 1;
+            while gri.index.contains_key(&new_key(&new_id)) {
+                n += 1;
+                new_id = format!("{}-{}", base, n);
+            }
+            rule.insert(Value::from("id"), Value::from(new_id.as_str()));
+            duplicate.renamed_to = Some(new_key(&new_id));
+            add_rule(gri, new_key(&new_id), id, rule, source, locations);
+        }
+    }
+    gri.duplicates.push(duplicate);
+}
+
+fn add_rule(
+    gri: &mut GenericRuleIndex,
+    key: String,
+    id: String,
+    rule: GenericRule,
+    source: RuleSource,
+    locations: YamlLocations,
+) {
+    gri.sources.insert(key.clone(), source);
+    gri.locations.insert(key.clone(), locations);
+    gri.ids.insert(key.clone(), id);
+    gri.index.insert(key, rule);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULE_FILE: &str = "tests/rules/multiple-rules.yaml";
+
+    #[test]
+    fn test_sources() {
+        let ri = GenericRuleIndex::from_path_simple(RULE_FILE).unwrap();
+
+        let source = ri.get_source("potentially-uninitialized-pointer").unwrap();
+        assert_eq!(
+            source,
+            &RuleSource {
+                file: RULE_FILE.to_string(),
+                index: 1,
+                start_line: 22,
+                end_line: 40,
             }
+        );
+
+        // the last rule ends at the end of the file.
+        let source = ri.find_source("tmp.tmpa1b2c3.memcpy-insecure-use").unwrap();
+        assert_eq!((source.index, source.end_line), (2, 54));
+        assert!(ri.find_source("insecure-use").is_none());
+
+        // rules added in code don't have a source.
+        let mut ri = ri;
+        let rule = ri.get_rule("memcpy-insecure-use").unwrap();
+        ri.insert("memcpy-insecure-use", rule);
+        assert!(ri.get_source("memcpy-insecure-use").is_none());
+    }
+
+    #[test]
+    fn test_templates() {
+        let ri = GenericRuleIndex::from_path_simple("tests/templates").unwrap();
+        // 4 + 2 generated rules and a normal rule.
+        assert_eq!(ri.len(), 7);
+
+        let rule = ri.get_rule("dangerous-call-system").unwrap();
+        assert_eq!(rule.get_severity().unwrap(), crate::Severity::Error);
+        assert_eq!(ri.get_source("dangerous-call-system").unwrap().index, 1);
+
+        // generated rules can be used in policies.
+        let policy = ri.create_policy(&vec![
+            "insecure-strcpy-c".to_string(),
+            "not-a-template".to_string(),
+        ]);
+        assert_eq!(policy.rules.len(), 2);
+
+        // templates with too many rules are skipped.
+        let options = IndexOptions {
+            max_template_rules: Some(3),
+            ..Default::default()
+        };
+        let (ri, report) =
+            GenericRuleIndex::from_paths_with_report(vec!["tests/templates"], None, None, &options)
+                .unwrap();
+        assert_eq!(ri.len(), 3);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].kind, LoadErrorKind::Template);
+    }
+
+    #[test]
+    fn test_qualified_ids() {
+        let options = IndexOptions {
+            complete: true,
+            root: Some("tests/rules".to_string()),
+            ..Default::default()
         };
+        let ri = GenericRuleIndex::from_paths_with_options(
+            vec!["tests/rules/cpp", RULE_FILE],
+            None,
+            None,
+            &options,
+        )
+        .unwrap();
 
-        // get the file index
-        let file_index: HashMap<String, GenericRule> = rule_file.create_index(&r, complete);
+        // every rule in a multi-rule file has its own key.
+        let mut keys: Vec<String> = ri
+            .get_ids()
+            .into_iter()
+            .filter(|k| k.starts_with("multiple-rules."))
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "multiple-rules.memcpy-insecure-use",
+                "multiple-rules.potentially-uninitialized-pointer",
+                "multiple-rules.snprintf-insecure-use",
+            ]
+        );
+
+        // short and qualified IDs.
+        assert_eq!(
+            ri.get_qualified_ids("memcpy-insecure-use"),
+            vec![
+                "cpp.memcpy-insecure-use.memcpy-insecure-use",
+                "multiple-rules.memcpy-insecure-use",
+            ]
+        );
+        assert_eq!(
+            ri.get_short_id("multiple-rules.memcpy-insecure-use"),
+            Some("memcpy-insecure-use")
+        );
+        assert!(ri.get_qualified_ids("not-a-rule").is_empty());
 
-        // merge it into the main index
-        rule_index.extend(file_index);
+        // check_ids reported by Semgrep.
+        assert_eq!(
+            ri.resolve_check_id("multiple-rules.memcpy-insecure-use"),
+            Some("multiple-rules.memcpy-insecure-use")
+        );
+        assert_eq!(
+            ri.resolve_check_id("tmp.tmpa1b2c3.cpp.memcpy-insecure-use.memcpy-insecure-use"),
+            Some("cpp.memcpy-insecure-use.memcpy-insecure-use")
+        );
+        assert!(ri.resolve_check_id("memcpy-insecure-use").is_none());
+        assert!(ri
+            .get_rule_by_check_id("tests.rules.multiple-rules.snprintf-insecure-use")
+            .is_some());
     }
 
-    if rule_index.keys().len() == 0 {
-        return Error::wrap_str("Rule index is empty.");
+    fn duplicates_index(
+        duplicates: DuplicateStrategy,
+        root: Option<&str>,
+    ) -> Result<GenericRuleIndex> {
+        let options = IndexOptions {
+            duplicates,
+            root: root.map(|r| r.to_string()),
+            ..Default::default()
+        };
+        GenericRuleIndex::from_paths_with_options(vec!["tests/duplicates"], None, None, &options)
+    }
+
+    fn message(ri: &GenericRuleIndex, rule_id: &str) -> String {
+        ri.get_rule(rule_id)
+            .unwrap()
+            .get_message()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_duplicates() {
+        // keep the last one by default.
+        let ri = GenericRuleIndex::from_path_simple("tests/duplicates").unwrap();
+        assert_eq!(ri.len(), 3);
+        assert_eq!(message(&ri, "duplicate"), "The second rule.");
+        assert_eq!(
+            ri.get_duplicates()
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<String>>(),
+            vec!["`duplicate` is defined in tests/duplicates/a.yaml:2 and tests/duplicates/b/c.yaml:8"]
+        );
+        assert_eq!(
+            ri.get_source("duplicate").unwrap().file,
+            "tests/duplicates/b/c.yaml"
+        );
+
+        let ri = duplicates_index(DuplicateStrategy::KeepFirst, None).unwrap();
+        assert_eq!(message(&ri, "duplicate"), "The first rule.");
+        assert_eq!(ri.get_duplicates().len(), 1);
+
+        let err = duplicates_index(DuplicateStrategy::Fail, None)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            &err,
+            Error::DuplicateRule { id, first, second }
+                if id == "duplicate"
+                    && first.file == "tests/duplicates/a.yaml"
+                    && second.file == "tests/duplicates/b/c.yaml"
+        ));
+        assert!(err
+            .to_string()
+            .contains("tests/duplicates/a.yaml:2 and tests/duplicates/b/c.yaml:8"));
+    }
+
+    #[test]
+    fn test_duplicates_rename() {
+        let ri = duplicates_index(DuplicateStrategy::Rename, Some("tests/duplicates")).unwrap();
+        assert_eq!(ri.len(), 4);
+        assert_eq!(message(&ri, "duplicate"), "The first rule.");
+        assert_eq!(message(&ri, "b.c.duplicate"), "The second rule.");
+        // the rule's ID is updated but it's still the same rule in the files.
+        let renamed = ri.get_rule("b.c.duplicate").unwrap();
+        assert_eq!(renamed.get_id().unwrap(), "b.c.duplicate");
+        assert_eq!(ri.get_short_id("b.c.duplicate"), Some("duplicate"));
+        assert_eq!(
+            ri.get_duplicates()[0].renamed_to.as_deref(),
+            Some("b.c.duplicate")
+        );
+
+        // complete IDs don't collide across files.
+        let options = IndexOptions {
+            complete: true,
+            duplicates: DuplicateStrategy::Fail,
+            ..Default::default()
+        };
+        let ri = GenericRuleIndex::from_paths_with_options(
+            vec!["tests/duplicates"],
+            None,
+            None,
+            &options,
+        )
+        .unwrap();
+        assert!(ri.get_duplicates().is_empty());
+    }
+
+    #[test]
+    fn test_load_report() {
+        let (ri, report) = GenericRuleIndex::from_paths_with_report(
+            vec!["tests/load/rules"],
+            None,
+            None,
+            &IndexOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(ri.get_ids(), vec!["good-rule"]);
+        assert_eq!(
+            report.loaded,
+            vec![
+                "tests/load/rules/bad-template.yaml",
+                "tests/load/rules/good.yaml"
+            ]
+        );
+
+        let errors: Vec<(&str, LoadErrorKind)> = report
+            .errors
+            .iter()
+            .map(|e| (e.file.as_str(), e.kind))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "tests/load/rules/bad-template.yaml",
+                    LoadErrorKind::Template
+                ),
+                ("tests/load/rules/good.yaml", LoadErrorKind::MissingId),
+                (
+                    "tests/load/rules/not-a-rule-file.yaml",
+                    LoadErrorKind::Parse
+                ),
+            ]
+        );
+        assert_eq!(
+            report.errors[0].message,
+            "Template variable `func` doesn't have values."
+        );
+
+        // strict mode returns the report as an error.
+        let options = IndexOptions {
+            strict: true,
+            ..Default::default()
+        };
+        match GenericRuleIndex::from_paths_with_options(
+            vec!["tests/load/rules"],
+            None,
+            None,
+            &options,
+        ) {
+            Err(Error::LoadError(r)) => assert_eq!(r, report),
+            _ => panic!("expected a load error"),
+        }
+        assert!(
+            GenericRuleIndex::from_paths_with_options(vec![RULE_FILE], None, None, &options)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_load_report_read_error() {
+        let dir = tempfile::Builder::new().prefix("load").tempdir().unwrap();
+        let file = dir.path().join("invalid-utf8.yaml");
+        std::fs::write(&file, [0xff, 0xfe, 0x00]).unwrap();
+
+        let err = GenericRuleIndex::from_paths_with_report(
+            vec![dir.path().to_str().unwrap()],
+            None,
+            None,
+            &IndexOptions::default(),
+        );
+        // the index is empty.
+        assert!(err.is_err());
+
+        let (_, report) = GenericRuleIndex::from_paths_with_report(
+            vec![dir.path().to_str().unwrap(), RULE_FILE],
+            None,
+            None,
+            &IndexOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].kind, LoadErrorKind::Read);
+        assert_eq!(report.errors[0].file, file.to_str().unwrap());
+    }
+
+    // loading the same files returns the same index, duplicates and sources
+    // regardless of the order the files are read (e.g., in parallel).
+    #[test]
+    fn test_deterministic() {
+        let load = || {
+            let options = IndexOptions {
+                duplicates: DuplicateStrategy::Rename,
+                ..Default::default()
+            };
+            GenericRuleIndex::from_paths_with_report(
+                vec!["tests/duplicates", "tests/rules", "tests/templates"],
+                None,
+                None,
+                &options,
+            )
+            .unwrap()
+        };
+        let (first, first_report) = load();
+        for _ in 0..5 {
+            let (ri, report) = load();
+            assert_eq!(ri.get_hashes(), first.get_hashes());
+            assert_eq!(ri.sources, first.sources);
+            assert_eq!(ri.get_duplicates(), first.get_duplicates());
+            assert_eq!(report, first_report);
+        }
+    }
+
+    #[test]
+    fn test_hashes() {
+        // the rules in this file are also in tests/rules/cpp.
+        let multiple = GenericRuleIndex::from_path_simple(RULE_FILE).unwrap();
+        let cpp = GenericRuleIndex::from_path_simple("tests/rules/cpp").unwrap();
+
+        let hashes = multiple.get_hashes();
+        assert_eq!(hashes.len(), 3);
+        for (id, hash) in hashes {
+            assert_eq!(cpp.get_hash(&id), Some(hash), "{}", id);
+        }
+        assert_ne!(
+            cpp.get_hash("memcpy-insecure-use"),
+            cpp.get_hash("snprintf-insecure-use")
+        );
+        assert!(cpp.get_hash("not-a-rule").is_none());
     }
-    Ok(rule_index)
 }
@@ -0,0 +1,299 @@
+// Read rule files from a local git repository at a revision.
+//
+// The files are read from the git object database with the `git` command so
+// the repository is not checked out and the working tree is not changed:
+// 1. `git ls-tree` lists the files in the revision.
+// 2. `git cat-file --batch` reads the selected files.
+//
+// Files are selected with the same logic as `find_files`: hidden files and
+// directories are skipped, only include extensions are used and files that
+// end in exclude are skipped. The file names are the same as the ones
+// `find_files` returns for the repository directory (e.g., `repo/rules/x.yaml`)
+// so qualified IDs and rule sources are the same as loading a checkout of
+// the revision.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use super::load_report::{FileContent, LoadErrorKind};
+use crate::error::{Error, Result};
+use crate::utils::is_included;
+
+// return the selected files in paths (relative to the root of the repository)
+// at revision, sorted by path. If paths is empty, the whole repository is
+// used.
+pub(crate) fn read_git_files(
+    repo: &str,
+    revision: &str,
+    paths: &[&str],
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> Result<Vec<FileContent>> {
+    // check the revision first for a better error message.
+    let commit = git(
+        repo,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", revision),
+        ],
+    )
+    .map_err(|_| Error::new(format!("{} is not a revision in {}.", revision, repo)))?;
+    let commit = String::from_utf8_lossy(&commit).trim().to_string();
+
+    // list the files: `<mode> <type> <object>\t<path>\0`.
+    let mut args = vec!["ls-tree", "-r", "-z", commit.as_str(), "--"];
+    args.extend(paths);
+    let tree = git(repo, &args)?;
+
+    let mut files: Vec<(String, String)> = Vec::new();
+    for line in tree.split(|b| *b == 0).filter(|l| !l.is_empty()) {
+        let line = String::from_utf8_lossy(line);
+        let (info, path) = match line.split_once('\t') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let info: Vec<&str> = info.split(' ').collect();
+        // skip symlinks and submodules.
+        if info.len() != 3 || info[0] == "120000" || info[1] != "blob" {
+            continue;
+        }
+        if path.split('/').any(|p| p.starts_with('.'))
+            || !is_included(Path::new(path), include, exclude)
+        {
+            continue;
+        }
+        files.push((path.to_string(), info[2].to_string()));
+    }
+    files.sort();
+
+    let objects: Vec<&str> = files.iter().map(|(_, object)| object.as_str()).collect();
+    let contents = read_objects(repo, &objects)?;
+    Ok(files
+        .into_iter()
+        .zip(contents)
+        .map(|((path, _), content)| {
+            let file = Path::new(repo).join(path).to_string_lossy().to_string();
+            (file, content)
+        })
+        .collect())
+}
+
+// run git in the repository and return stdout.
+fn git(repo: &str, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|e| Error::new(format!("Error running git: {}", e)))?;
+    if !output.status.success() {
+        return Error::wrap_string(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+// read the objects with one `git cat-file --batch`, in order.
+fn read_objects(
+    repo: &str,
+    objects: &[&str],
+) -> Result<Vec<std::result::Result<String, (LoadErrorKind, String)>>> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| Error::new(format!("Error running git: {}", e)))?;
+
+    // write the objects in another thread so git doesn't block on a full
+    // stdout while we are still writing.
+    let mut stdin = child.stdin.take().unwrap();
+    let input: String = objects.iter().map(|o| format!("{}\n", o)).collect();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let contents = read_batch(&mut stdout, objects);
+
+    // always clean up, even if reading failed: closing stdout makes git exit
+    // so the writer thread is not blocked on a full pipe and git is waited
+    // for. A read error is returned before the writer and git errors.
+    drop(stdout);
+    let written = writer
+        .join()
+        .map_err(|_| Error::new("Error writing to git.".to_string()));
+    let waited = child.wait();
+    let contents = contents?;
+    written??;
+    waited?;
+    Ok(contents)
+}
+
+// read the `git cat-file --batch` output for the objects, in order.
+fn read_batch<R: BufRead>(
+    stdout: &mut R,
+    objects: &[&str],
+) -> Result<Vec<std::result::Result<String, (LoadErrorKind, String)>>> {
+    let mut contents = Vec::new();
+    for object in objects {
+        // `<object> blob <size>\n<content>\n` or `<object> missing\n`.
+        let mut header = String::new();
+        stdout.read_line(&mut header)?;
+        let size = match header.trim_end().split(' ').collect::<Vec<&str>>()[..] {
+            [_, "blob", size] => size.parse::<usize>().ok(),
+            _ => None,
+        };
+        let size = match size {
+            Some(size) => size,
+            None => {
+                contents.push(Err((
+                    LoadErrorKind::Read,
+                    format!("git object {}: {}", object, header.trim_end()),
+                )));
+                continue;
+            }
+        };
+        let mut content = vec![0; size + 1];
+        stdout.read_exact(&mut content)?;
+        content.truncate(size);
+        contents.push(String::from_utf8(content).map_err(|e| (LoadErrorKind::Read, e.to_string())));
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GenericRuleIndex, IndexOptions};
+    use std::fs;
+
+    const RULE: &str = "rules:
+  - id: rule-1
+    message: rule 1
+    severity: ERROR
+    languages: [python]
+    pattern: eval(...)
+";
+
+    fn run(repo: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    // create a repository with two commits. The first one is tagged `v1`.
+    fn setup() -> tempfile::TempDir {
+        let dir = tempfile::Builder::new().prefix("git").tempdir().unwrap();
+        let repo = dir.path();
+        run(repo, &["init", "-q"]);
+        fs::create_dir_all(repo.join("rules/python")).unwrap();
+        fs::create_dir_all(repo.join(".github")).unwrap();
+        fs::write(repo.join("rules/python/eval.yaml"), RULE).unwrap();
+        fs::write(repo.join("rules/python/eval.test.yaml"), "# test").unwrap();
+        fs::write(repo.join(".github/hidden.yaml"), RULE).unwrap();
+        run(repo, &["add", "-A"]);
+        run(repo, &["commit", "-q", "-m", "v1"]);
+        run(repo, &["tag", "v1"]);
+
+        fs::write(
+            repo.join("rules/python/eval.yaml"),
+            format!("# comment\n{}", RULE.replace("eval(...)", "exec(...)")),
+        )
+        .unwrap();
+        fs::write(
+            repo.join("rules/python/new.yaml"),
+            RULE.replace("rule-1", "rule-2"),
+        )
+        .unwrap();
+        run(repo, &["add", "-A"]);
+        run(repo, &["commit", "-q", "-m", "v2"]);
+        dir
+    }
+
+    #[test]
+    fn test_read_git_files() {
+        let dir = setup();
+        let repo = dir.path().to_str().unwrap();
+
+        let files = read_git_files(repo, "v1", &[], &None, &None).unwrap();
+        let names: Vec<&str> = files.iter().map(|(f, _)| f.as_str()).collect();
+        let eval = format!("{}/rules/python/eval.yaml", repo);
+        assert_eq!(names, [eval.as_str()]);
+        assert_eq!(files[0].1.as_ref().unwrap(), RULE);
+
+        let files = read_git_files(repo, "HEAD", &["rules"], &None, &None).unwrap();
+        assert_eq!(files.len(), 2);
+        let files = read_git_files(repo, "HEAD", &["docs"], &None, &None).unwrap();
+        assert!(files.is_empty());
+
+        assert!(read_git_files(repo, "v3", &[], &None, &None).is_err());
+        assert!(read_git_files("does-not-exist", "HEAD", &[], &None, &None).is_err());
+    }
+
+    // the index is the same as loading the checked out files.
+    #[test]
+    fn test_from_git() {
+        let dir = setup();
+        let repo = dir.path().to_str().unwrap();
+        let options = IndexOptions {
+            complete: true,
+            root: Some(repo.to_string()),
+            ..Default::default()
+        };
+
+        let (head, report) =
+            GenericRuleIndex::from_git(repo, "HEAD", vec!["rules"], None, None, &options).unwrap();
+        let rules = format!("{}/rules", repo);
+        let (checkout, _) =
+            GenericRuleIndex::from_paths_with_report(vec![&rules], None, None, &options).unwrap();
+        assert_eq!(head.get_hashes(), checkout.get_hashes());
+        let key = "rules.python.eval.rule-1";
+        assert_eq!(head.get_source(key), checkout.get_source(key));
+        assert_eq!(head.get_source(key).unwrap().start_line, 3);
+        assert_eq!(report.loaded.len(), 2);
+
+        let (v1, _) = GenericRuleIndex::from_git(repo, "v1", vec![], None, None, &options).unwrap();
+        assert_eq!(v1.get_ids(), [key]);
+        assert_ne!(v1.get_hash(key), head.get_hash(key));
+    }
+
+    // lint findings point at the lines in the revision, not in the working
+    // tree. The working tree has an extra comment line at the top.
+    #[test]
+    fn test_lint_from_git() {
+        use crate::{LintCheck, Linter};
+
+        let dir = setup();
+        let repo = dir.path().to_str().unwrap();
+        let (v1, _) =
+            GenericRuleIndex::from_git(repo, "v1", vec![], None, None, &IndexOptions::default())
+                .unwrap();
+        let linter = Linter::with_checks(vec![
+            LintCheck::MissingCategory,
+            LintCheck::MessageWithoutMetavariables,
+        ]);
+        let findings = linter.lint_index(&v1);
+        let locations: Vec<(Option<usize>, Option<usize>)> =
+            findings.iter().map(|f| (f.line, f.column)).collect();
+        // the missing metadata points at the rule, the message at its key.
+        assert_eq!(locations, [(Some(2), Some(5)), (Some(3), Some(5))]);
+        let eval = format!("{}/rules/python/eval.yaml", repo);
+        assert_eq!(findings[0].file.as_deref(), Some(eval.as_str()));
+    }
+}
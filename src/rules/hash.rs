@@ -0,0 +1,155 @@
+// Semantic content hash of rules.
+//
+// The hash is calculated over a canonical form of the rule instead of the
+// YAML text, so key order, quoting, block scalars and comments don't change
+// it. The canonical form:
+// 1. sorts the keys of every mapping. Lists keep their order.
+// 2. removes the trailing newlines from strings (`|` and `|-` are the same
+//    pattern).
+// 3. is written as JSON-like text and hashed with SHA-256.
+
+use serde_yaml::Value;
+use sha2::{Digest, Sha256};
+
+use super::semgrep_generic_rule::GenericRule;
+
+/// return the hex encoded SHA-256 hash of the rule's canonical form.
+pub(crate) fn content_hash(rule: &GenericRule) -> String {
+    let mut canonical = String::new();
+    write_canonical(&mut canonical, &Value::Mapping(rule.clone()));
+
+    Sha256::digest(canonical.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// write the canonical form of a value. Every value is prefixed with its type
+// so `1` and `"1"` are different.
+fn write_canonical(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Number(n) => {
+            out.push('n');
+            out.push_str(&n.to_string());
+        }
+        Value::String(s) => write_string(out, s.trim_end_matches('\n')),
+        Value::Sequence(items) => {
+            out.push('[');
+            for item in items {
+                write_canonical(out, item);
+                out.push(',');
+            }
+            out.push(']');
+        }
+        Value::Mapping(map) => {
+            let mut entries: Vec<(String, String)> = map
+                .iter()
+                .map(|(k, v)| {
+                    let (mut key, mut value) = (String::new(), String::new());
+                    write_canonical(&mut key, k);
+                    write_canonical(&mut value, v);
+                    (key, value)
+                })
+                .collect();
+            entries.sort();
+
+            out.push('{');
+            for (k, v) in entries {
+                out.push_str(&k);
+                out.push(':');
+                out.push_str(&v);
+                out.push(',');
+            }
+            out.push('}');
+        }
+        Value::Tagged(tagged) => {
+            out.push('!');
+            write_string(out, &tagged.tag.to_string());
+            write_canonical(out, &tagged.value);
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('s');
+    out.push_str(&serde_json::to_string(s).unwrap_or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(yaml: &str) -> String {
+        content_hash(&serde_yaml::from_str::<GenericRule>(yaml).unwrap())
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let original = hash(
+            r#"
+id: foo
+message: calling foo
+severity: WARNING
+languages: [c]
+patterns:
+  - pattern: |
+      foo($X)
+  - pattern-not: foo(1)
+"#,
+        );
+        assert_eq!(original.len(), 64);
+
+        // key order, quoting, block scalars and comments don't matter.
+        let same = hash(
+            r#"
+# a comment
+patterns:
+  - pattern: "foo($X)"
+  - pattern-not: 'foo(1)' # another comment
+languages:
+  - c
+severity: WARNING
+message: |-
+  calling foo
+id: foo
+"#,
+        );
+        assert_eq!(original, same);
+
+        // changing a pattern changes the hash.
+        let changed = hash(
+            r#"
+id: foo
+message: calling foo
+severity: WARNING
+languages: [c]
+patterns:
+  - pattern: foo($Y)
+  - pattern-not: foo(1)
+"#,
+        );
+        assert_ne!(original, changed);
+
+        // the order of the items in lists is kept.
+        let reordered = hash(
+            r#"
+id: foo
+message: calling foo
+severity: WARNING
+languages: [c]
+patterns:
+  - pattern-not: foo(1)
+  - pattern: foo($X)
+"#,
+        );
+        assert_ne!(original, reordered);
+    }
+
+    #[test]
+    fn test_types() {
+        assert_ne!(hash("id: '1'"), hash("id: 1"));
+        assert_ne!(hash("id: 'true'"), hash("id: true"));
+    }
+}
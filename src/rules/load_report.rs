@@ -0,0 +1,99 @@
+// What happened while loading rule and policy files.
+//
+// The loaders skip files that cannot be read or deserialized (and rules that
+// cannot be indexed) instead of failing. The report lists what was skipped
+// and why so callers can show it or fail in strict mode.
+
+use std::fmt;
+
+// ----- START LoadError
+
+/// why a file (or a rule in it) was skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LoadErrorKind {
+    /// the file could not be read.
+    Read,
+    /// the file is not a valid rule or policy file.
+    Parse,
+    /// a rule template in the file could not be expanded.
+    Template,
+    /// a rule in the file doesn't have an ID.
+    MissingId,
+}
+
+impl fmt::Display for LoadErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self {
+            LoadErrorKind::Read => "read",
+            LoadErrorKind::Parse => "parse",
+            LoadErrorKind::Template => "template",
+            LoadErrorKind::MissingId => "missing-id",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+/// a file or rule that was skipped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadError {
+    pub file: String,
+    pub kind: LoadErrorKind,
+    pub message: String,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.file, self.kind, self.message)
+    }
+}
+
+// the content of a file that was read, or why it could not be read. Used by
+// the loaders that don't read the files from disk.
+#[cfg(any(feature = "archive", feature = "git"))]
+pub(crate) type FileContent = (String, std::result::Result<String, (LoadErrorKind, String)>);
+
+// ----- END LoadError
+
+// ----- START LoadReport
+
+/// the result of loading files into an index.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// files that were loaded, including files where some rules were skipped.
+    pub loaded: Vec<String>,
+    /// files and rules that were skipped.
+    pub errors: Vec<LoadError>,
+}
+
+impl LoadReport {
+    /// return true if nothing was skipped.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    // add an error to the report.
+    pub(crate) fn push(&mut self, file: &str, kind: LoadErrorKind, message: String) {
+        self.errors.push(LoadError {
+            file: file.to_string(),
+            kind,
+            message,
+        });
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "loaded {} files, skipped {}",
+            self.loaded.len(),
+            self.errors.len()
+        )?;
+        for e in &self.errors {
+            write!(f, "\n{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+// ----- END LoadReport
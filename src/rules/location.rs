@@ -0,0 +1,328 @@
+// Maps nodes in a YAML document to their position in the source text.
+//
+// serde_yaml does not keep positions, so we run the document through
+// yaml-rust2's event parser and record where each node starts. Nodes are
+// identified by their JSON pointer (e.g., `/rules/0/patterns/1`) which is also
+// what the schema validator reports.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+/// a position in a YAML file. Both line and column start at 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    fn from_marker(mark: &Marker) -> Position {
+        Position {
+            line: mark.line(),
+            column: mark.col() + 1,
+        }
+    }
+}
+
+/// where an indexed rule came from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RuleSource {
+    /// path of the rule file.
+    pub file: String,
+    /// position of the rule in the file's `rules` (starts at 0).
+    pub index: usize,
+    /// first line of the rule in the file (starts at 1).
+    pub start_line: usize,
+    /// last line of the rule in the file. Trailing empty lines and comments
+    /// are not part of the rule.
+    pub end_line: usize,
+}
+
+// ----- START YamlLocations
+
+/// positions of the nodes in a YAML document keyed by JSON pointer. For map
+/// entries the position is the position of the key.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct YamlLocations {
+    positions: HashMap<String, Position>,
+}
+
+impl YamlLocations {
+    // parse the YAML string and record the position of every node. Returns an
+    // empty index if the string cannot be parsed.
+    pub(crate) fn from_yaml(yaml: &str) -> YamlLocations {
+        let mut recorder = Recorder::default();
+        let mut parser = Parser::new_from_str(yaml);
+        match parser.load(&mut recorder, false) {
+            Ok(_) => YamlLocations {
+                positions: recorder.positions,
+            },
+            Err(_) => YamlLocations::default(),
+        }
+    }
+
+    // return the position of the node at `pointer`. If the node doesn't exist
+    // (e.g., a missing key), return the position of the closest parent.
+    pub(crate) fn get(&self, pointer: &str) -> Option<Position> {
+        let mut current = pointer;
+        loop {
+            if let Some(pos) = self.positions.get(current) {
+                return Some(*pos);
+            }
+            match current.rfind('/') {
+                Some(i) => current = &current[..i],
+                None => return None,
+            }
+        }
+    }
+
+    // return the positions of the nodes in each rule in a rule file. The
+    // pointers are relative to the rule (e.g., `/languages/1` instead of
+    // `/rules/0/languages/1`) and the empty pointer is the rule itself.
+    pub(crate) fn rule_locations(&self) -> Vec<YamlLocations> {
+        let mut results: Vec<YamlLocations> = Vec::new();
+        while self
+            .positions
+            .contains_key(&format!("/rules/{}", results.len()))
+        {
+            let prefix = format!("/rules/{}", results.len());
+            let positions = self
+                .positions
+                .iter()
+                .filter_map(|(pointer, pos)| {
+                    let rest = pointer.strip_prefix(&prefix)?;
+                    match rest.is_empty() || rest.starts_with('/') {
+                        true => Some((rest.to_string(), *pos)),
+                        false => None,
+                    }
+                })
+                .collect();
+            results.push(YamlLocations { positions });
+        }
+        results
+    }
+
+    // return the (start, end) lines of each rule in a rule file. yaml must be
+    // the string that was used to create the locations. A rule ends before
+    // the next rule, the next top-level key or the end of the file.
+    pub(crate) fn rule_spans(&self, yaml: &str) -> Vec<(usize, usize)> {
+        let lines: Vec<&str> = yaml.lines().collect();
+
+        let mut starts: Vec<usize> = Vec::new();
+        while let Some(pos) = self.positions.get(&format!("/rules/{}", starts.len())) {
+            starts.push(pos.line);
+        }
+
+        let rules_line = self.positions.get("/rules").map(|p| p.line).unwrap_or(0);
+        let last_line = self
+            .positions
+            .iter()
+            .filter(|(p, pos)| p.matches('/').count() == 1 && pos.line > rules_line)
+            .map(|(_, pos)| pos.line - 1)
+            .min()
+            .unwrap_or(lines.len());
+
+        starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let mut end = match starts.get(i + 1) {
+                    Some(next) => next.saturating_sub(1),
+                    None => last_line,
+                }
+                .max(start);
+                // skip empty lines and comments between the rules.
+                while end > start && is_blank_or_comment(lines.get(end - 1).copied()) {
+                    end -= 1;
+                }
+                (start, end)
+            })
+            .collect()
+    }
+}
+
+// ----- END YamlLocations
+
+// return true if the line is empty or only has a comment.
+fn is_blank_or_comment(line: Option<&str>) -> bool {
+    match line.map(|l| l.trim()) {
+        Some(l) => l.is_empty() || l.starts_with('#'),
+        None => true,
+    }
+}
+
+// escape a key for use in a JSON pointer. See RFC 6901.
+pub(crate) fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// a container that is currently being parsed.
+enum Frame {
+    Map {
+        pointer: String,
+        // the key of the current entry, None if we expect a key next.
+        key: Option<String>,
+        // yaml-rust2 doesn't report the start of block maps accurately, so
+        // maps that are not values of a map entry use the position of their
+        // first key instead.
+        use_first_key: bool,
+    },
+    Seq {
+        pointer: String,
+        index: usize,
+    },
+}
+
+#[derive(Default)]
+struct Recorder {
+    positions: HashMap<String, Position>,
+    stack: Vec<Frame>,
+}
+
+impl Recorder {
+    // called for every node. Returns the pointer of the node or None if the
+    // node is a map key.
+    fn enter_node(&mut self, key_text: Option<&str>, mark: &Marker) -> Option<String> {
+        let pos = Position::from_marker(mark);
+        let pointer = match self.stack.last_mut() {
+            None => String::new(),
+            Some(Frame::Seq { pointer, index }) => {
+                let p = format!("{}/{}", pointer, index);
+                *index += 1;
+                p
+            }
+            Some(Frame::Map {
+                pointer,
+                key,
+                use_first_key,
+            }) => match key.take() {
+                // this node is a key.
+                None => {
+                    if *use_first_key {
+                        self.positions.insert(pointer.clone(), pos);
+                        *use_first_key = false;
+                    }
+                    // complex keys (maps or sequences) are not supported.
+                    let k = key_text.unwrap_or("?").to_string();
+                    let p = format!("{}/{}", pointer, escape_pointer(&k));
+                    self.positions.entry(p).or_insert(pos);
+                    *key = Some(k);
+                    return None;
+                }
+                // this node is the value of the current entry. The position
+                // of the key has already been recorded.
+                Some(k) => format!("{}/{}", pointer, escape_pointer(&k)),
+            },
+        };
+        self.positions.entry(pointer.clone()).or_insert(pos);
+        Some(pointer)
+    }
+
+    // scalars and aliases are complete as soon as they are seen.
+    fn leaf(&mut self, key_text: Option<&str>, mark: &Marker) {
+        if self.enter_node(key_text, mark).is_some() {
+            self.exit_node();
+        }
+    }
+
+    // after a value is complete, the parent map expects a new key.
+    fn exit_node(&mut self) {
+        if let Some(Frame::Map { key, .. }) = self.stack.last_mut() {
+            *key = None;
+        }
+    }
+}
+
+impl MarkedEventReceiver for Recorder {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::Scalar(value, ..) => self.leaf(Some(&value), &mark),
+            Event::Alias(_) => self.leaf(None, &mark),
+            Event::MappingStart(..) => {
+                let use_first_key = !matches!(self.stack.last(), Some(Frame::Map { .. }));
+                // a map used as a key is pushed with a dummy pointer.
+                let pointer = self.enter_node(None, &mark).unwrap_or_default();
+                self.stack.push(Frame::Map {
+                    pointer,
+                    key: None,
+                    use_first_key,
+                });
+            }
+            Event::SequenceStart(..) => {
+                let pointer = self.enter_node(None, &mark).unwrap_or_default();
+                self.stack.push(Frame::Seq { pointer, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+                self.exit_node();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locations() {
+        let yaml = "rules:\n- id: one\n  patterns:\n    - pattern: foo()\n    - pattern-not: |\n        bar()\n  message: hi\n";
+        let loc = YamlLocations::from_yaml(yaml);
+
+        assert_eq!(loc.get("/rules"), Some(Position { line: 1, column: 1 }));
+        assert_eq!(loc.get("/rules/0"), Some(Position { line: 2, column: 3 }));
+        assert_eq!(
+            loc.get("/rules/0/id"),
+            Some(Position { line: 2, column: 3 })
+        );
+        assert_eq!(
+            loc.get("/rules/0/patterns/1"),
+            Some(Position { line: 5, column: 7 })
+        );
+        assert_eq!(
+            loc.get("/rules/0/message"),
+            Some(Position { line: 7, column: 3 })
+        );
+        // missing nodes return the closest parent.
+        assert_eq!(
+            loc.get("/rules/0/severity"),
+            Some(Position { line: 2, column: 3 })
+        );
+    }
+
+    #[test]
+    fn test_rule_spans() {
+        let yaml =
+            "rules:\n- id: one\n  pattern: |\n    foo()\n\n# two\n- id: two\n  pattern: bar()\n\n";
+        let loc = YamlLocations::from_yaml(yaml);
+        assert_eq!(loc.rule_spans(yaml), vec![(2, 4), (7, 8)]);
+
+        let yaml = "rules: [{id: one}, {id: two}]\n";
+        let loc = YamlLocations::from_yaml(yaml);
+        assert_eq!(loc.rule_spans(yaml), vec![(1, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_rule_locations() {
+        let yaml =
+            "rules:\n- id: one\n  languages: [c]\n- id: two\n  languages:\n    - c\n    - cpp\n";
+        let rules = YamlLocations::from_yaml(yaml).rule_locations();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].get(""), Some(Position { line: 2, column: 3 }));
+        assert_eq!(
+            rules[1].get("/languages/1"),
+            Some(Position { line: 7, column: 7 })
+        );
+        // missing nodes return the closest parent.
+        assert_eq!(
+            rules[1].get("/message"),
+            Some(Position { line: 4, column: 3 })
+        );
+        // `/rules/1` is not a prefix of `/rules/10`.
+        assert!(rules[0].get("/languages/0").unwrap().line < 4);
+    }
+}
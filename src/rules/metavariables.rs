@@ -0,0 +1,88 @@
+// Helpers to find metavariables (e.g., `$FUNC` or `$...ARGS`) in patterns and
+// messages.
+
+// return the metavariables in `text` in the order they appear. Duplicates are
+// removed. Both regular (`$X`) and ellipsis (`$...X`) metavariables are
+// returned with their `$` prefix.
+pub(crate) fn find_metavariables(text: &str) -> Vec<String> {
+    let mut results: Vec<String> = Vec::new();
+    let bytes = text.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'$' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut j = i + 1;
+        if text[j..].starts_with("...") {
+            j += 3;
+        }
+        // metavariables start with an uppercase letter or `_`.
+        if j < bytes.len() && (bytes[j].is_ascii_uppercase() || bytes[j] == b'_') {
+            while j < bytes.len()
+                && (bytes[j].is_ascii_uppercase() || bytes[j].is_ascii_digit() || bytes[j] == b'_')
+            {
+                j += 1;
+            }
+            let name = &text[start..j];
+            if !results.iter().any(|r| r == name) {
+                results.push(name.to_string());
+            }
+            i = j;
+        } else {
+            i += 1;
+        }
+    }
+    results
+}
+
+// return the metavariables bound by a regex (e.g., `pattern-regex`). Semgrep
+// binds each named capture group (`(?P<NAME>...)`) to `$NAME`.
+pub(crate) fn find_regex_metavariables(regex: &str) -> Vec<String> {
+    let mut results: Vec<String> = Vec::new();
+    for prefix in ["(?P<", "(?<"] {
+        for (start, _) in regex.match_indices(prefix) {
+            let rest = &regex[start + prefix.len()..];
+            let group = match rest.find('>') {
+                Some(end) => &rest[..end],
+                None => continue,
+            };
+            // skip lookbehinds such as `(?<=...)`.
+            if group.is_empty() || !group.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                continue;
+            }
+            let name = format!("${}", group);
+            if !results.contains(&name) {
+                results.push(name);
+            }
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_metavariables() {
+        assert_eq!(
+            find_metavariables("$BUF is passed to $FUNC($...ARGS). $BUF again, $x and $ not."),
+            vec!["$BUF", "$FUNC", "$...ARGS"]
+        );
+        assert_eq!(find_metavariables("$_ = $VAR1"), vec!["$_", "$VAR1"]);
+        assert!(find_metavariables("no metavariables $ here $1").is_empty());
+    }
+
+    #[test]
+    fn test_find_regex_metavariables() {
+        assert_eq!(
+            find_regex_metavariables(r"(?P<KEY>\w+)\s*=\s*(?<VALUE>.*)$"),
+            vec!["$KEY", "$VALUE"]
+        );
+        assert!(find_regex_metavariables(r"^foo(\d+)$").is_empty());
+        assert!(find_regex_metavariables(r"(?<=a)b(?<!c)>").is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+// Rewrite deprecated rule syntax into the current equivalents.
+//
+// Supported migrations:
+// 1. `languages: python` (a string) -> `languages: [python]`.
+// 2. the `none` language -> `regex` (also in `metavariable-pattern.language`).
+// 3. lowercase severities (`warning`) -> uppercase (`WARNING`).
+// 4. `paths.include` and `paths.exclude` strings -> lists.
+//
+// Constructs that cannot be migrated automatically are only detected: they
+// are reported and left as-is. `pattern-where-python` is always reported
+// because it runs arbitrary Python and must be rewritten by hand with
+// `metavariable-comparison` or `metavariable-regex`.
+//
+// serde_yaml doesn't keep comments so migrate_path doesn't rewrite files with
+// comments, they are reported instead.
+
+use std::fmt;
+
+use serde_yaml::{Mapping, Value};
+
+use super::format::has_comments;
+use super::location::escape_pointer;
+use super::semgrep_generic_rule::{GenericRule, GenericRuleExt, GenericRuleFile};
+use crate::utils::{find_files, read_file_to_string, write_string_to_file};
+use crate::Result;
+
+// ----- START MigrationReport
+
+/// a migrated construct or one that could not be migrated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationItem {
+    /// ID of the rule. Empty if the rule doesn't have an ID.
+    pub rule_id: String,
+    /// JSON pointer to the construct inside the rule, e.g., `/languages`.
+    /// Empty with an empty rule_id if the item is about the whole file.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for MigrationItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.rule_id.is_empty() && self.path.is_empty() {
+            // an item about the whole file.
+            return write!(f, "{}", self.message);
+        }
+        write!(f, "{}{}: {}", self.rule_id, self.path, self.message)
+    }
+}
+
+/// the result of migrating a rule file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// constructs that were rewritten.
+    pub migrated: Vec<MigrationItem>,
+    /// constructs that are deprecated but could not be rewritten.
+    pub unsupported: Vec<MigrationItem>,
+}
+
+impl MigrationReport {
+    /// return true if the migration changed the rules.
+    pub fn changed(&self) -> bool {
+        !self.migrated.is_empty()
+    }
+}
+
+/// the result of migrating a file on disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileMigration {
+    pub file: String,
+    pub report: MigrationReport,
+}
+
+// ----- END MigrationReport
+
+impl GenericRuleFile {
+    /// rewrite deprecated syntax in the rules and report what was changed and
+    /// what could not be migrated.
+    pub fn migrate(&mut self) -> MigrationReport {
+        let mut report = MigrationReport::default();
+        for rule in &mut self.rules {
+            migrate_rule(rule, &mut report);
+        }
+        report
+    }
+}
+
+/// migrate all the rule files in path (see `GenericRuleFile::migrate`). If
+/// `write` is true, the files with migrated constructs are rewritten with the
+/// canonical formatting, otherwise the files are only checked. Files with
+/// comments are not rewritten because the comments would be lost. Returns a
+/// report for every file with migrated or unsupported constructs. Files that
+/// cannot be read or parsed and files that were not rewritten because of
+/// their comments are reported with an unsupported item.
+pub fn migrate_path(path: &str, write: bool) -> Result<Vec<FileMigration>> {
+    let mut results: Vec<FileMigration> = Vec::new();
+    let mut files = find_files(path, &None, &None);
+    files.sort();
+
+    for file in files {
+        let (mut rule_file, commented) = match read_rule_file(&file) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let mut report = MigrationReport::default();
+                report
+                    .unsupported
+                    .push(file_item(format!("the file could not be migrated: {}", e)));
+                results.push(FileMigration { file, report });
+                continue;
+            }
+        };
+        let mut report = rule_file.migrate();
+        if write && report.changed() {
+            if commented {
+                report.unsupported.push(file_item(
+                    "the file has comments and was not rewritten, \
+                     migrate it manually to keep them"
+                        .to_string(),
+                ));
+            } else {
+                write_string_to_file(&file, &rule_file.to_formatted_string()?)?;
+            }
+        }
+        if report.changed() || !report.unsupported.is_empty() {
+            results.push(FileMigration { file, report });
+        }
+    }
+    Ok(results)
+}
+
+// read and parse a rule file. Also returns true if the file has comments.
+fn read_rule_file(file: &str) -> Result<(GenericRuleFile, bool)> {
+    let content = read_file_to_string(file)?;
+    let value: Value = serde_yaml::from_str(&content)?;
+    let commented = has_comments(&content);
+    Ok((serde_yaml::from_value(value)?, commented))
+}
+
+// an item about the whole file instead of a rule.
+fn file_item(message: String) -> MigrationItem {
+    MigrationItem {
+        rule_id: String::new(),
+        path: String::new(),
+        message,
+    }
+}
+
+// ----- START migrations
+
+fn migrate_rule(rule: &mut GenericRule, report: &mut MigrationReport) {
+    let id = rule.get_id().unwrap_or_default().to_string();
+    let mut migrated = |path: &str, message: String| {
+        report.migrated.push(MigrationItem {
+            rule_id: id.clone(),
+            path: path.to_string(),
+            message,
+        })
+    };
+
+    // 1. and 2. languages.
+    if let Some(Value::String(lang)) = rule.get("languages").cloned() {
+        rule.insert(
+            Value::from("languages"),
+            Value::Sequence(vec![Value::from(lang.as_str())]),
+        );
+        migrated(
+            "/languages",
+            format!("`languages: {}` converted to a list", lang),
+        );
+    }
+    if let Some(Value::Sequence(langs)) = rule.get_mut("languages") {
+        for (i, lang) in langs.iter_mut().enumerate() {
+            if lang.as_str() == Some("none") {
+                *lang = Value::from("regex");
+                migrated(
+                    &format!("/languages/{}", i),
+                    "language `none` renamed to `regex`".to_string(),
+                );
+            }
+        }
+    }
+
+    // 3. severity.
+    if let Some(Value::String(severity)) = rule.get_mut("severity") {
+        let upper = severity.to_uppercase();
+        if *severity != upper {
+            migrated(
+                "/severity",
+                format!("severity `{}` changed to `{}`", severity, upper),
+            );
+            *severity = upper;
+        }
+    }
+
+    // 4. paths.
+    if let Some(Value::Mapping(paths)) = rule.get_mut("paths") {
+        for key in ["include", "exclude"] {
+            if let Some(Value::String(p)) = paths.get(key).cloned() {
+                paths.insert(
+                    Value::from(key),
+                    Value::Sequence(vec![Value::from(p.as_str())]),
+                );
+                migrated(
+                    &format!("/paths/{}", key),
+                    format!("`paths.{}` converted to a list", key),
+                );
+            }
+        }
+    }
+
+    // nested constructs.
+    let mut nested = MigrationReport::default();
+    for (key, value) in rule.iter_mut() {
+        let pointer = format!("/{}", escape_pointer(key.as_str().unwrap_or_default()));
+        migrate_nested(value, &pointer, &mut nested);
+    }
+    for item in nested
+        .migrated
+        .iter_mut()
+        .chain(nested.unsupported.iter_mut())
+    {
+        item.rule_id = id.clone();
+    }
+    report.migrated.extend(nested.migrated);
+    report.unsupported.extend(nested.unsupported);
+}
+
+// walk the rule and migrate `metavariable-pattern.language: none`. Report
+// `pattern-where-python` as unsupported. rule_id is set by the caller.
+fn migrate_nested(value: &mut Value, pointer: &str, report: &mut MigrationReport) {
+    match value {
+        Value::Mapping(map) => {
+            if let Some(Value::Mapping(mp)) = map.get_mut("metavariable-pattern") {
+                migrate_language(mp, &format!("{}/metavariable-pattern", pointer), report);
+            }
+            if map.contains_key("pattern-where-python") {
+                report.unsupported.push(MigrationItem {
+                    rule_id: String::new(),
+                    path: format!("{}/pattern-where-python", pointer),
+                    message: "`pattern-where-python` is not supported by Semgrep anymore, \
+                              rewrite it with `metavariable-comparison` or \
+                              `metavariable-regex`"
+                        .to_string(),
+                });
+            }
+            for (key, child) in map.iter_mut() {
+                let child_pointer = format!(
+                    "{}/{}",
+                    pointer,
+                    escape_pointer(key.as_str().unwrap_or_default())
+                );
+                migrate_nested(child, &child_pointer, report);
+            }
+        }
+        Value::Sequence(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                migrate_nested(item, &format!("{}/{}", pointer, i), report);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn migrate_language(map: &mut Mapping, pointer: &str, report: &mut MigrationReport) {
+    if let Some(lang) = map.get_mut("language") {
+        if lang.as_str() == Some("none") {
+            *lang = Value::from("regex");
+            report.migrated.push(MigrationItem {
+                rule_id: String::new(),
+                path: format!("{}/language", pointer),
+                message: "language `none` renamed to `regex`".to_string(),
+            });
+        }
+    }
+}
+
+// ----- END migrations
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GenericRuleFieldsExt;
+
+    const LEGACY: &str = "tests/migrate/legacy.yaml";
+
+    #[test]
+    fn test_migrate() {
+        let mut rule_file = GenericRuleFile::from_file(LEGACY).unwrap();
+        let report = rule_file.migrate();
+
+        let migrated: Vec<String> = report.migrated.iter().map(|m| m.to_string()).collect();
+        assert_eq!(
+            migrated,
+            vec![
+                "legacy-search/languages: `languages: none` converted to a list",
+                "legacy-search/languages/0: language `none` renamed to `regex`",
+                "legacy-search/severity: severity `warning` changed to `WARNING`",
+                "legacy-search/paths/include: `paths.include` converted to a list",
+                "legacy-nested/patterns/1/metavariable-pattern/language: language `none` renamed to `regex`",
+            ]
+        );
+        assert_eq!(report.unsupported.len(), 1);
+        assert_eq!(report.unsupported[0].rule_id, "legacy-nested");
+        assert_eq!(
+            report.unsupported[0].path,
+            "/patterns/2/pattern-where-python"
+        );
+
+        // the migrated rules are valid, except for pattern-where-python.
+        let violations = rule_file.validate_schema();
+        assert!(violations
+            .iter()
+            .all(|v| v.rule_id.as_deref() == Some("legacy-nested")));
+
+        // migrating again doesn't change anything.
+        let report = rule_file.migrate();
+        assert!(!report.changed());
+        assert_eq!(report.unsupported.len(), 1);
+    }
+
+    #[test]
+    fn test_migrate_path() {
+        // check only, don't write.
+        let results = migrate_path("tests/migrate", false).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].file, LEGACY);
+        assert!(results[0].report.changed());
+
+        // write to a copy. find_files skips hidden directories such as the
+        // default `.tmpXXXX`.
+        let dir = tempfile::Builder::new()
+            .prefix("migrate")
+            .tempdir()
+            .unwrap();
+        let copy = dir.path().join("legacy.yaml");
+        std::fs::copy(LEGACY, &copy).unwrap();
+        // files that cannot be parsed and files with comments are reported.
+        std::fs::write(dir.path().join("broken.yaml"), "rules: [").unwrap();
+        let legacy = std::fs::read_to_string(LEGACY).unwrap();
+        let commented = format!("# keep this comment.\n{}", legacy);
+        std::fs::write(dir.path().join("commented.yaml"), &commented).unwrap();
+
+        let results = migrate_path(dir.path().to_str().unwrap(), true).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].file.ends_with("broken.yaml"));
+        assert!(!results[0].report.changed());
+        assert!(results[0].report.unsupported[0]
+            .message
+            .starts_with("the file could not be migrated"));
+
+        // the file with comments is not rewritten.
+        assert!(results[1].file.ends_with("commented.yaml"));
+        assert!(results[1].report.changed());
+        let last = results[1].report.unsupported.last().unwrap();
+        assert_eq!(last.path, "");
+        assert!(last.message.contains("has comments"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("commented.yaml")).unwrap(),
+            commented
+        );
+
+        let migrated = GenericRuleFile::from_file(copy.to_str().unwrap()).unwrap();
+        assert_eq!(migrated.rules[0].get_languages().unwrap(), vec!["regex"]);
+        // the second run only reports the unsupported construct.
+        let results = migrate_path(dir.path().to_str().unwrap(), true).unwrap();
+        assert!(!results[2].report.changed());
+        assert_eq!(results[2].report.unsupported.len(), 1);
+    }
+}
@@ -1,3 +1,24 @@
+#[cfg(feature = "archive")]
+pub(crate) mod archive;
+pub(crate) mod builder;
+pub(crate) mod cache;
+pub(crate) mod diff;
+pub(crate) mod format;
 pub(crate) mod generic_rule_index;
+#[cfg(feature = "git")]
+pub(crate) mod git;
+pub(crate) mod hash;
+pub(crate) mod load_report;
+pub(crate) mod location;
+pub(crate) mod metavariables;
+pub(crate) mod migrate;
+pub(crate) mod patterns;
 pub(crate) mod policy;
+pub(crate) mod query;
+pub(crate) mod rule;
+pub(crate) mod schema;
 pub(crate) mod semgrep_generic_rule;
+pub(crate) mod split;
+pub(crate) mod template;
+#[cfg(feature = "watch")]
+pub(crate) mod watch;
@@ -0,0 +1,339 @@
+// Enumerate the patterns inside a rule.
+//
+// The rule is walked recursively through every operator that can contain
+// patterns (`patterns`, `pattern-either`, taint sources/sinks/sanitizers,
+// `metavariable-pattern`, join rules, etc.). Each pattern string is returned
+// with its operator, its JSON pointer in the rule, the metavariables it binds
+// and the metavariables it references. Only positive operators (`pattern`,
+// `pattern-inside` and `pattern-regex`) bind metavariables. Exclusions
+// (`pattern-not`, etc.), the patterns nested inside them (e.g.,
+// `pattern-not: {patterns: [...]}`) and join conditions only reference them.
+
+use std::fmt;
+
+use serde_yaml::Value;
+
+use super::location::escape_pointer;
+use super::metavariables::{find_metavariables, find_regex_metavariables};
+use super::semgrep_generic_rule::GenericRule;
+
+// keys that never contain patterns.
+const SKIPPED_KEYS: [&str; 6] = [
+    "metadata",
+    "options",
+    "paths",
+    "message",
+    "fix",
+    "fix-regex",
+];
+
+// ----- START PatternKind
+
+/// the operator of a pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PatternKind {
+    Pattern,
+    PatternNot,
+    PatternInside,
+    PatternNotInside,
+    PatternRegex,
+    PatternNotRegex,
+    /// a condition in a join rule's `on` list, e.g., `a.$X == b.$X`.
+    JoinOn,
+}
+
+impl PatternKind {
+    /// return the YAML key of the operator, e.g., `pattern-inside`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PatternKind::Pattern => "pattern",
+            PatternKind::PatternNot => "pattern-not",
+            PatternKind::PatternInside => "pattern-inside",
+            PatternKind::PatternNotInside => "pattern-not-inside",
+            PatternKind::PatternRegex => "pattern-regex",
+            PatternKind::PatternNotRegex => "pattern-not-regex",
+            PatternKind::JoinOn => "on",
+        }
+    }
+
+    /// return the operator for a YAML key or None if the key is not a pattern
+    /// operator. `on` is not included because it's only a pattern in joins.
+    pub fn from_key(key: &str) -> Option<PatternKind> {
+        match key {
+            "pattern" => Some(PatternKind::Pattern),
+            "pattern-not" => Some(PatternKind::PatternNot),
+            "pattern-inside" => Some(PatternKind::PatternInside),
+            "pattern-not-inside" => Some(PatternKind::PatternNotInside),
+            "pattern-regex" => Some(PatternKind::PatternRegex),
+            "pattern-not-regex" => Some(PatternKind::PatternNotRegex),
+            _ => None,
+        }
+    }
+
+    /// return true if the operator binds the metavariables in the pattern.
+    /// Exclusions and join conditions don't bind anything.
+    pub fn binds(&self) -> bool {
+        matches!(
+            self,
+            PatternKind::Pattern | PatternKind::PatternInside | PatternKind::PatternRegex
+        )
+    }
+
+    /// return true if the operator is an exclusion, e.g., `pattern-not`.
+    /// Nothing inside an exclusion binds metavariables.
+    pub fn is_negative(&self) -> bool {
+        matches!(
+            self,
+            PatternKind::PatternNot | PatternKind::PatternNotInside | PatternKind::PatternNotRegex
+        )
+    }
+
+    /// return true if the pattern is a regular expression.
+    pub fn is_regex(&self) -> bool {
+        matches!(
+            self,
+            PatternKind::PatternRegex | PatternKind::PatternNotRegex
+        )
+    }
+}
+
+impl fmt::Display for PatternKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// ----- END PatternKind
+
+// ----- START PatternEntry
+
+/// a pattern inside a rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternEntry {
+    pub kind: PatternKind,
+    /// JSON pointer to the pattern inside the rule, e.g., `/patterns/0/pattern`.
+    pub path: String,
+    pub pattern: String,
+    /// metavariables bound by the pattern in the order they appear. Empty for
+    /// exclusions, patterns nested inside an exclusion and join conditions,
+    /// see `PatternKind::binds`.
+    pub bound: Vec<String>,
+    /// metavariables in the pattern in the order they appear, e.g., `$X` or
+    /// `$...ARGS`. Named capture groups are returned for regex patterns.
+    pub referenced: Vec<String>,
+}
+
+impl PatternEntry {
+    // negated is true if the pattern is nested inside an exclusion.
+    fn new(kind: PatternKind, path: String, pattern: &str, negated: bool) -> PatternEntry {
+        let referenced = if kind.is_regex() {
+            find_regex_metavariables(pattern)
+        } else {
+            find_metavariables(pattern)
+        };
+        let bound = match kind.binds() && !negated {
+            true => referenced.clone(),
+            false => Vec::new(),
+        };
+        PatternEntry {
+            kind,
+            path,
+            pattern: pattern.to_string(),
+            bound,
+            referenced,
+        }
+    }
+}
+
+impl fmt::Display for PatternEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.path, self.kind, self.pattern)
+    }
+}
+
+// ----- END PatternEntry
+
+/// return all the patterns in the rule in document order.
+pub(crate) fn find_patterns(rule: &GenericRule) -> Vec<PatternEntry> {
+    let mut results: Vec<PatternEntry> = Vec::new();
+    walk(&Value::Mapping(rule.clone()), "", false, &mut results);
+    results
+}
+
+/// return the metavariables bound by the rule's patterns in the order they
+/// appear. Duplicates are removed.
+pub(crate) fn find_rule_metavariables(rule: &GenericRule) -> Vec<String> {
+    let mut results: Vec<String> = Vec::new();
+    for entry in find_patterns(rule) {
+        for mv in entry.bound {
+            if !results.contains(&mv) {
+                results.push(mv);
+            }
+        }
+    }
+    results
+}
+
+// negated is true below an exclusion (e.g., `pattern-not`) and stays true for
+// everything nested in it.
+fn walk(value: &Value, pointer: &str, negated: bool, results: &mut Vec<PatternEntry>) {
+    match value {
+        Value::Mapping(map) => {
+            let is_join = map.contains_key("on") && map.contains_key("rules");
+            for (key, child) in map {
+                let key = match key.as_str() {
+                    Some(k) => k,
+                    None => continue,
+                };
+                if SKIPPED_KEYS.contains(&key) {
+                    continue;
+                }
+                let child_pointer = format!("{}/{}", pointer, escape_pointer(key));
+
+                match (PatternKind::from_key(key), child) {
+                    (Some(kind), Value::String(pattern)) => {
+                        results.push(PatternEntry::new(kind, child_pointer, pattern, negated));
+                    }
+                    // join conditions.
+                    (None, Value::Sequence(conditions)) if is_join && key == "on" => {
+                        for (i, condition) in conditions.iter().enumerate() {
+                            if let Some(c) = condition.as_str() {
+                                results.push(PatternEntry::new(
+                                    PatternKind::JoinOn,
+                                    format!("{}/{}", child_pointer, i),
+                                    c,
+                                    negated,
+                                ));
+                            }
+                        }
+                    }
+                    (kind, _) => {
+                        let negated = negated || kind.is_some_and(|k| k.is_negative());
+                        walk(child, &child_pointer, negated, results)
+                    }
+                }
+            }
+        }
+        Value::Sequence(items) => {
+            for (i, item) in items.iter().enumerate() {
+                walk(item, &format!("{}/{}", pointer, i), negated, results);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::semgrep_generic_rule::GenericRuleFile;
+
+    fn patterns(file: &str, index: usize) -> Vec<String> {
+        let rule_file = GenericRuleFile::from_file(file).unwrap();
+        find_patterns(&rule_file.rules[index])
+            .iter()
+            .map(|p| format!("{} {:?}", p.path, p.bound))
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        assert_eq!(
+            patterns("tests/patterns/patterns.yaml", 0),
+            vec![
+                r#"/patterns/0/pattern-inside ["$FUNC", "$...ARGS"]"#,
+                r#"/patterns/1/pattern-either/0/pattern ["$BUF", "$SRC"]"#,
+                r#"/patterns/1/pattern-either/1/pattern-regex ["$NAME"]"#,
+                r#"/patterns/2/pattern-not []"#,
+                r#"/patterns/3/metavariable-pattern/patterns/0/pattern ["$LEN"]"#,
+                r#"/patterns/3/metavariable-pattern/patterns/1/pattern-not-regex []"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_taint() {
+        assert_eq!(
+            patterns("tests/patterns/patterns.yaml", 1),
+            vec![
+                r#"/pattern-sources/0/pattern ["$ARG"]"#,
+                r#"/pattern-sanitizers/0/patterns/0/pattern-inside ["$X"]"#,
+                r#"/pattern-sanitizers/0/patterns/1/pattern ["$X"]"#,
+                r#"/pattern-sinks/0/pattern ["$CMD"]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            patterns("tests/patterns/patterns.yaml", 2),
+            vec![
+                r#"/join/rules/0/pattern ["$VAR"]"#,
+                r#"/join/rules/1/pattern ["$VAR"]"#,
+                r#"/join/on/0 []"#,
+            ]
+        );
+
+        let rule_file = GenericRuleFile::from_file("tests/patterns/patterns.yaml").unwrap();
+        let kinds: Vec<PatternKind> = find_patterns(&rule_file.rules[2])
+            .iter()
+            .map(|p| p.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                PatternKind::Pattern,
+                PatternKind::Pattern,
+                PatternKind::JoinOn
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_rule_metavariables() {
+        let rule_file = GenericRuleFile::from_file("tests/patterns/patterns.yaml").unwrap();
+        assert_eq!(
+            find_rule_metavariables(&rule_file.rules[0]),
+            vec!["$FUNC", "$...ARGS", "$BUF", "$SRC", "$NAME", "$LEN"]
+        );
+    }
+
+    // metavariables that only appear in exclusions are referenced but not
+    // bound.
+    #[test]
+    fn test_exclusions_do_not_bind() {
+        let rule_file = GenericRuleFile::from_file("tests/patterns/patterns.yaml").unwrap();
+        let rule = &rule_file.rules[3];
+        let entries = find_patterns(rule);
+        let not = &entries[1];
+        assert_eq!(not.kind, PatternKind::PatternNot);
+        assert_eq!(not.referenced, vec!["$FUNC", "$X"]);
+        assert!(not.bound.is_empty());
+        assert_eq!(find_rule_metavariables(rule), vec!["$FUNC"]);
+
+        // patterns nested inside exclusions don't bind anything either.
+        let entries = find_patterns(&rule_file.rules[4]);
+        let bound: Vec<String> = entries
+            .iter()
+            .map(|p| format!("{} {:?} {:?}", p.path, p.bound, p.referenced))
+            .collect();
+        assert_eq!(
+            bound,
+            vec![
+                r#"/patterns/0/pattern ["$FUNC"] ["$FUNC"]"#,
+                r#"/patterns/1/pattern-not/patterns/0/pattern [] ["$FUNC", "$X"]"#,
+                r#"/patterns/1/pattern-not/patterns/1/pattern-either/0/pattern [] ["$X"]"#,
+                r#"/patterns/2/pattern-not-inside/pattern-either/0/pattern [] ["$Y"]"#,
+                r#"/patterns/2/pattern-not-inside/pattern-either/1/patterns/0/pattern-inside [] ["$Z"]"#,
+                r#"/patterns/3/pattern-not/pattern [] ["$FUNC", "$W"]"#,
+            ]
+        );
+        assert_eq!(find_rule_metavariables(&rule_file.rules[4]), vec!["$FUNC"]);
+
+        // join conditions reference the metavariables of the join rules.
+        let entries = find_patterns(&rule_file.rules[2]);
+        assert_eq!(entries[2].referenced, vec!["$VAR"]);
+        assert!(entries[2].bound.is_empty());
+    }
+}
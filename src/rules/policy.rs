@@ -1,8 +1,14 @@
 // ----- START Policy
 
+#[cfg(feature = "archive")]
+use super::archive::{read_archive_bytes, read_archive_file};
+use super::cache::IndexCache;
 use super::generic_rule_index::GenericRuleIndex;
+#[cfg(feature = "archive")]
+use super::load_report::FileContent;
+use super::load_report::{LoadErrorKind, LoadReport};
 use crate::error::{Error, Result};
-use crate::utils::{find_files, read_file_to_string, write_string_to_file};
+use crate::utils::{find_files_in_paths, map_ordered, read_file_to_string, write_string_to_file};
 
 use log::error;
 use serde::{Deserialize, Serialize};
@@ -89,6 +95,11 @@ impl Policy {
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
+
+    // returns the IDs of the rules in the policy.
+    pub fn get_rules(&self) -> Vec<String> {
+        self.rules.clone()
+    }
 }
 
 // ----- END Policy
@@ -138,8 +149,8 @@ impl PolicyIndex {
     ) -> Result<PolicyIndex> {
         let mut pi = PolicyIndex::new();
 
-        match create_policy_index(paths, include, exclude, ri) {
-            Ok(index) => pi.index = index,
+        match create_policy_index(paths, include, exclude, ri, false) {
+            Ok((index, _)) => pi.index = index,
             Err(e) => return Error::wrap_string(e.to_string()),
         };
         // these can be moved into the Ok() arm of the match, too.
@@ -151,6 +162,72 @@ impl PolicyIndex {
         Ok(pi)
     }
 
+    // same as from_paths but also returns a report of the policy files that
+    // were loaded and skipped. If strict is true, return an error
+    // (Error::LoadError) if any file was skipped.
+    pub fn from_paths_with_report(
+        paths: Vec<&str>,
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        ri: &GenericRuleIndex,
+        strict: bool,
+    ) -> Result<(PolicyIndex, LoadReport)> {
+        let mut pi = PolicyIndex::new();
+        let (index, report) = create_policy_index(paths, include, exclude, ri, strict)?;
+        pi.index = index;
+        pi.index.insert("all".to_string(), create_all_policy(ri)?);
+        Ok((pi, report))
+    }
+
+    // same as from_paths_with_report but only parses the files that are not
+    // in the cache or have changed since they were cached. The cache is
+    // updated, use `IndexCache::save` to write it to disk.
+    pub fn from_paths_cached(
+        paths: Vec<&str>,
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        ri: &GenericRuleIndex,
+        strict: bool,
+        cache: &mut IndexCache,
+    ) -> Result<(PolicyIndex, LoadReport)> {
+        let policy_files = find_files_in_paths(paths, &include, &exclude);
+        let (index, report) =
+            policy_index_from_parsed(cache.load_policies(policy_files), ri, strict)?;
+        let mut pi = PolicyIndex::new();
+        pi.index = index;
+        pi.index.insert("all".to_string(), create_all_policy(ri)?);
+        Ok((pi, report))
+    }
+
+    // return a new PolicyIndex populated with the policy files in a `.tar`,
+    // `.tar.gz` or `.zip` archive without unpacking it. Files are selected
+    // with include and exclude like `find_files`.
+    #[cfg(feature = "archive")]
+    pub fn from_archive(
+        path: &str,
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        ri: &GenericRuleIndex,
+        strict: bool,
+    ) -> Result<(PolicyIndex, LoadReport)> {
+        let entries = read_archive_file(path, &include, &exclude)?;
+        policy_index_from_archive(entries, ri, strict)
+    }
+
+    // same as from_archive but for an archive in memory. The format is
+    // detected from the content.
+    #[cfg(feature = "archive")]
+    pub fn from_archive_bytes(
+        bytes: &[u8],
+        include: Option<Vec<&str>>,
+        exclude: Option<Vec<&str>>,
+        ri: &GenericRuleIndex,
+        strict: bool,
+    ) -> Result<(PolicyIndex, LoadReport)> {
+        let entries = read_archive_bytes(bytes, &include, &exclude)?;
+        policy_index_from_archive(entries, ri, strict)
+    }
+
     // same as from_paths but only creates a policy for a single path.
     pub fn from_path(
         path: &str,
@@ -185,38 +262,41 @@ impl PolicyIndex {
 
 // find all policies with extensions in include and no files that end in
 // exclude. Deserialize each into a Policy and store them in the index where
-// key: policy name and value: the Policy object.
+// key: policy name and value: the Policy object. Files that cannot be read or
+// deserialized are skipped and added to the report, unless strict is true.
 fn create_policy_index(
     paths: Vec<&str>,
     include: Option<Vec<&str>>,
     exclude: Option<Vec<&str>>,
     ri: &GenericRuleIndex,
-) -> Result<HashMap<String, Policy>> {
-    let mut policy_index: HashMap<String, Policy> = HashMap::new();
-
-    let mut policy_files: Vec<String> = Vec::new();
-
-    for p in paths {
-        policy_files.extend(find_files(p, &include, &exclude));
-    }
+    strict: bool,
+) -> Result<(HashMap<String, Policy>, LoadReport)> {
+    let policy_files = find_files_in_paths(paths, &include, &exclude);
+
+    // read and parse the files (in parallel with the `parallel` feature), then
+    // add them to the index in order.
+    let parsed = map_ordered(policy_files, parse_policy_file);
+    policy_index_from_parsed(parsed, ri, strict)
+}
 
-    for policy_file_path in policy_files {
-        let policy_text = match read_file_to_string(&policy_file_path) {
-            Ok(cn) => cn,
-            Err(e) => {
-                error!("Error reading file: {}", e.to_string());
-                continue;
-            }
-        };
+// populate the parsed policies and add them to a new index in order.
+fn policy_index_from_parsed(
+    parsed: Vec<(String, ParsedPolicy)>,
+    ri: &GenericRuleIndex,
+    strict: bool,
+) -> Result<(HashMap<String, Policy>, LoadReport)> {
+    let mut policy_index: HashMap<String, Policy> = HashMap::new();
+    let mut report = LoadReport::default();
 
-        // create a Policy object from the string.
-        let mut policy_object = match Policy::from_yaml(&policy_text) {
-            Ok(rf) => rf,
-            Err(e) => {
-                error!("Error deserializing file: {}", e.to_string());
+    for (policy_file_path, result) in parsed {
+        let mut policy_object = match result {
+            Ok(p) => p,
+            Err((kind, message)) => {
+                report.push(&policy_file_path, kind, message);
                 continue;
             }
         };
+        report.loaded.push(policy_file_path.clone());
 
         // populate the Policy.
         policy_object.populate(ri)?;
@@ -224,11 +304,57 @@ fn create_policy_index(
         // add it to the main index.
         policy_index.insert(policy_object.name.clone(), policy_object);
     }
+    if strict && !report.is_ok() {
+        return Err(Error::LoadError(report));
+    }
     // return an error if the index is empty.
     if policy_index.keys().len() == 0 {
         return Error::wrap_str("Policy index is empty.");
     }
-    Ok(policy_index)
+    Ok((policy_index, report))
+}
+
+// create a PolicyIndex from the policy files read from an archive.
+#[cfg(feature = "archive")]
+fn policy_index_from_archive(
+    entries: Vec<FileContent>,
+    ri: &GenericRuleIndex,
+    strict: bool,
+) -> Result<(PolicyIndex, LoadReport)> {
+    let parsed = map_ordered(entries, |(file, content)| {
+        let parsed = content.and_then(|c| parse_policy_content(&c));
+        (file, parsed)
+    });
+    let mut pi = PolicyIndex::new();
+    let (index, report) = policy_index_from_parsed(parsed, ri, strict)?;
+    pi.index = index;
+    pi.index.insert("all".to_string(), create_all_policy(ri)?);
+    Ok((pi, report))
+}
+
+// the parsed policy, or why the file was skipped.
+pub(crate) type ParsedPolicy = std::result::Result<Policy, (LoadErrorKind, String)>;
+
+// read and deserialize a policy file.
+fn parse_policy_file(file: String) -> (String, ParsedPolicy) {
+    let policy_text = match read_file_to_string(&file) {
+        Ok(cn) => cn,
+        Err(e) => {
+            error!("Error reading file: {}", e);
+            return (file, Err((LoadErrorKind::Read, e.to_string())));
+        }
+    };
+    let parsed = parse_policy_content(&policy_text);
+    (file, parsed)
+}
+
+// deserialize the content of a policy file.
+pub(crate) fn parse_policy_content(policy_text: &str) -> ParsedPolicy {
+    // create a Policy object from the string.
+    Policy::from_yaml(policy_text).map_err(|e| {
+        error!("Error deserializing file: {}", e);
+        (LoadErrorKind::Parse, e.to_string())
+    })
 }
 
 // creates a policy named `all` from all the rules in the index.
@@ -238,3 +364,36 @@ fn create_all_policy(ri: &GenericRuleIndex) -> Result<Policy> {
     all_policy.populate(ri)?;
     Ok(all_policy)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_report() {
+        let ri = GenericRuleIndex::from_path_simple("tests/load/rules").unwrap();
+        let (pi, report) = PolicyIndex::from_paths_with_report(
+            vec!["tests/load/policies"],
+            None,
+            None,
+            &ri,
+            false,
+        )
+        .unwrap();
+
+        let mut ids = pi.get_ids();
+        ids.sort();
+        assert_eq!(ids, vec!["all", "good-policy"]);
+        assert_eq!(report.loaded, vec!["tests/load/policies/good-policy.yaml"]);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(
+            report.errors[0].file,
+            "tests/load/policies/broken-policy.yaml"
+        );
+        assert_eq!(report.errors[0].kind, LoadErrorKind::Parse);
+
+        let strict =
+            PolicyIndex::from_paths_with_report(vec!["tests/load/policies"], None, None, &ri, true);
+        assert!(matches!(strict, Err(Error::LoadError(_))));
+    }
+}
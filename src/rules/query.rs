@@ -0,0 +1,437 @@
+// Query the rules in a GenericRuleIndex.
+//
+// A query filters rules by ID (glob), language, severity, mode and metadata.
+// Different filters must all match, values of the same filter are
+// alternatives. E.g., "ERROR taint rules for python or java with a CWE-89
+// metadata.cwe":
+//
+// RuleQuery::new()
+//     .severity(Severity::Error)
+//     .mode(Mode::Taint)
+//     .language("python")
+//     .language("java")
+//     .metadata("cwe", "CWE-89")
+//
+// The same query in the string syntax:
+//
+// severity:ERROR mode:taint lang:python,java metadata.cwe:CWE-89
+//
+// Terms are `key:value` separated by whitespace. Values are comma-separated
+// alternatives and can be quoted (`metadata.category:"best practice"`). Keys:
+// `id` (glob with `*` and `?`), `lang` or `language`, `severity`, `mode` and
+// `metadata.<path>` where path is dot-separated (e.g., `metadata.owasp`).
+
+use serde_yaml::Value;
+
+use super::generic_rule_index::GenericRuleIndex;
+use super::rule::{canonical_language, Mode, Severity};
+use super::semgrep_generic_rule::{
+    GenericRule, GenericRuleExt, GenericRuleFieldsExt, GenericRuleFile,
+};
+use crate::{Error, Result};
+
+// ----- START RuleQuery
+
+/// a query over the rules in a GenericRuleIndex. An empty query matches all
+/// rules.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleQuery {
+    ids: Vec<String>,
+    languages: Vec<String>,
+    severities: Vec<Severity>,
+    modes: Vec<Mode>,
+    // (path in metadata, alternative values).
+    metadata: Vec<(Vec<String>, Vec<String>)>,
+}
+
+impl RuleQuery {
+    /// create a query that matches all rules.
+    pub fn new() -> RuleQuery {
+        RuleQuery::default()
+    }
+
+    /// match rules where the key in the index or the rule ID matches the glob
+    /// (`*` matches any characters, `?` matches one character).
+    pub fn id(mut self, glob: &str) -> Self {
+        self.ids.push(glob.to_string());
+        self
+    }
+
+    /// match rules that have this language (case insensitive). Aliases match
+    /// the canonical name, e.g., `py` matches rules with `python`.
+    pub fn language(mut self, language: &str) -> Self {
+        self.languages.push(canonical_language(language));
+        self
+    }
+
+    /// match rules with this severity.
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severities.push(severity);
+        self
+    }
+
+    /// match rules with this mode. Rules without a `mode` are search rules
+    /// unless they have taint, join or extract keys.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.modes.push(mode);
+        self
+    }
+
+    /// match rules where the metadata at path (dot-separated, e.g., `cwe` or
+    /// `references.owasp`) contains value as a whole word (case insensitive),
+    /// e.g., `CWE-89` matches `CWE-89: SQL Injection` but not `CWE-895`. If
+    /// the metadata is a list, any item can contain value.
+    pub fn metadata(self, path: &str, value: &str) -> Self {
+        self.metadata_any(path, &[value])
+    }
+
+    /// same as metadata but any of the values can match.
+    pub fn metadata_any(mut self, path: &str, values: &[&str]) -> Self {
+        let path: Vec<String> = path.split('.').map(|p| p.to_string()).collect();
+        let values: Vec<String> = values.iter().map(|v| v.to_lowercase()).collect();
+        self.metadata.push((path, values));
+        self
+    }
+
+    /// parse a query in the string syntax, e.g.,
+    /// `severity:ERROR mode:taint lang:python metadata.cwe:CWE-89`.
+    pub fn parse(query: &str) -> Result<RuleQuery> {
+        let mut q = RuleQuery::new();
+        for (key, values) in split_terms(query)? {
+            let values: Vec<&str> = values.iter().map(|v| v.as_str()).collect();
+            match key.as_str() {
+                "id" => values.iter().for_each(|v| q.ids.push(v.to_string())),
+                "lang" | "language" => values
+                    .iter()
+                    .for_each(|v| q.languages.push(canonical_language(v))),
+                "severity" => {
+                    for v in values {
+                        q.severities.push(Severity::from_str_ignore_case(v)?);
+                    }
+                }
+                "mode" => {
+                    for v in values {
+                        q.modes.push(Mode::from_str_ignore_case(v)?);
+                    }
+                }
+                k => match k.strip_prefix("metadata.") {
+                    Some(path) if !path.is_empty() => q = q.metadata_any(path, &values),
+                    _ => {
+                        return Error::wrap_string(format!("Unknown query key: `{}`.", key));
+                    }
+                },
+            }
+        }
+        Ok(q)
+    }
+
+    /// return true if the rule matches the query. key is the rule's key in the
+    /// index, it's also matched against the `id` globs.
+    pub fn matches(&self, key: &str, rule: &GenericRule) -> bool {
+        self.matches_id(key, rule)
+            && self.matches_language(rule)
+            && (self.severities.is_empty()
+                || rule
+                    .get_severity()
+                    .map(|s| self.severities.contains(&s))
+                    .unwrap_or(false))
+            && (self.modes.is_empty()
+                || rule
+                    .get_mode()
+                    .map(|m| self.modes.contains(&m))
+                    .unwrap_or(false))
+            && self.matches_metadata(rule)
+    }
+
+    fn matches_id(&self, key: &str, rule: &GenericRule) -> bool {
+        let id = rule.get_id().unwrap_or_default();
+        self.ids.is_empty()
+            || self
+                .ids
+                .iter()
+                .any(|glob| glob_match(glob, key) || glob_match(glob, id))
+    }
+
+    fn matches_language(&self, rule: &GenericRule) -> bool {
+        self.languages.is_empty()
+            || rule
+                .get_languages()
+                .map(|langs| {
+                    langs
+                        .iter()
+                        .any(|l| self.languages.contains(&canonical_language(l)))
+                })
+                .unwrap_or(false)
+    }
+
+    fn matches_metadata(&self, rule: &GenericRule) -> bool {
+        let metadata = match rule.get("metadata") {
+            Some(m) => m,
+            None => return self.metadata.is_empty(),
+        };
+        self.metadata.iter().all(|(path, values)| {
+            let mut current = Some(metadata);
+            for key in path {
+                current = current.and_then(|c| c.get(key.as_str()));
+            }
+            match current {
+                Some(v) => contains_any(v, values),
+                None => false,
+            }
+        })
+    }
+}
+
+// ----- END RuleQuery
+
+impl GenericRuleIndex {
+    // return the keys of the rules that match the query, sorted.
+    pub fn query(&self, query: &RuleQuery) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .get_index()
+            .iter()
+            .filter(|(key, rule)| query.matches(key, rule))
+            .map(|(key, _)| key.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    // return a rule file with the rules that match the query. It can be
+    // passed to Semgrep.
+    pub fn query_rules(&self, query: &RuleQuery) -> GenericRuleFile {
+        self.create_policy(&self.query(query))
+    }
+}
+
+// ----- START helpers
+
+// split the query into (key, values). Values are comma-separated and can be
+// quoted with `"`.
+fn split_terms(query: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let mut terms: Vec<(String, Vec<String>)> = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    loop {
+        while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == ':' || c.is_whitespace() {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        if chars.next() != Some(':') {
+            return Error::wrap_string(format!("Query terms must be `key:value`, got `{}`.", key));
+        }
+
+        let mut values: Vec<String> = Vec::new();
+        let mut value = String::new();
+        let mut quoted = false;
+        while let Some(&c) = chars.peek() {
+            match c {
+                '"' => quoted = !quoted,
+                ',' if !quoted => values.push(std::mem::take(&mut value)),
+                c if c.is_whitespace() && !quoted => break,
+                c => value.push(c),
+            }
+            chars.next();
+        }
+        if quoted {
+            return Error::wrap_string(format!("Unterminated quote in the value of `{}`.", key));
+        }
+        values.push(value);
+        if values.iter().any(|v| v.is_empty()) {
+            return Error::wrap_string(format!("Empty value for `{}`.", key));
+        }
+        terms.push((key, values));
+    }
+    Ok(terms)
+}
+
+// return true if the value (or any item if it's a list) contains any of the
+// lowercase values as a whole word, see contains_word.
+fn contains_any(value: &Value, values: &[String]) -> bool {
+    match value {
+        Value::Sequence(items) => items.iter().any(|i| contains_any(i, values)),
+        Value::String(s) => {
+            let s = s.to_lowercase();
+            values.iter().any(|v| contains_word(&s, v))
+        }
+        Value::Number(n) => values.iter().any(|v| n.to_string() == *v),
+        Value::Bool(b) => values.iter().any(|v| b.to_string() == *v),
+        _ => false,
+    }
+}
+
+// return true if text contains word and the match is not in the middle of a
+// word, e.g., `cwe-89` is in `cwe-89: sql injection` but not in `cwe-895`. A
+// letter or digit at the start or end of word must not be next to another one.
+fn contains_word(text: &str, word: &str) -> bool {
+    let is_alphanumeric = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    let first = word.chars().next();
+    let last = word.chars().next_back();
+    text.char_indices().any(|(i, _)| {
+        text[i..].starts_with(word)
+            && !(is_alphanumeric(first) && is_alphanumeric(text[..i].chars().next_back()))
+            && !(is_alphanumeric(last) && is_alphanumeric(text[i + word.len()..].chars().next()))
+    })
+}
+
+// match text against a glob where `*` matches any characters (including none)
+// and `?` matches one character.
+fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    // position of the last `*` and the text position it matched up to.
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if g < glob.len() && (glob[g] == '?' || glob[g] == text[t]) {
+            g += 1;
+            t += 1;
+        } else if g < glob.len() && glob[g] == '*' {
+            star = Some((g, t));
+            g += 1;
+        } else if let Some((sg, st)) = star {
+            // let the last `*` match one more character.
+            g = sg + 1;
+            t = st + 1;
+            star = Some((sg, st + 1));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+// ----- END helpers
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY_FILE: &str = "tests/query/rules.yaml";
+
+    fn query(q: &RuleQuery) -> Vec<String> {
+        GenericRuleIndex::from_path_simple(QUERY_FILE)
+            .unwrap()
+            .query(q)
+    }
+
+    #[test]
+    fn test_query() {
+        // an empty query matches everything.
+        assert_eq!(query(&RuleQuery::new()).len(), 6);
+
+        let q = RuleQuery::new()
+            .severity(Severity::Error)
+            .mode(Mode::Taint)
+            .language("Python")
+            .metadata("cwe", "cwe-89");
+        assert_eq!(query(&q), vec!["python-sqli"]);
+
+        // language aliases match the canonical name and the other aliases.
+        let q = RuleQuery::new().language("py");
+        assert_eq!(query(&q), vec!["python-eval", "python-sqli", "regex-dos"]);
+        let q = RuleQuery::parse("lang:PY,c++").unwrap();
+        assert_eq!(
+            query(&q),
+            vec!["cpp-strcpy", "python-eval", "python-sqli", "regex-dos"]
+        );
+
+        // values of the same filter are alternatives.
+        let q = RuleQuery::new().language("java").language("c");
+        assert_eq!(query(&q), vec!["c-gets", "java-sqli"]);
+
+        // metadata values match whole words.
+        let q = RuleQuery::new().metadata("cwe", "CWE-89");
+        assert_eq!(query(&q), vec!["java-sqli", "python-sqli"]);
+        let q = RuleQuery::new().metadata("cwe", "CWE-8");
+        assert!(query(&q).is_empty());
+        let q = RuleQuery::new().metadata("category", "practice");
+        assert_eq!(query(&q), vec!["c-gets"]);
+
+        // nested metadata and numbers.
+        let q = RuleQuery::new().metadata("references.owasp", "A03:2021");
+        assert_eq!(query(&q), vec!["java-sqli", "python-sqli"]);
+        let q = RuleQuery::new().metadata("confidence", "2");
+        assert_eq!(query(&q), vec!["python-eval"]);
+
+        let q = RuleQuery::new().id("*-sqli");
+        assert_eq!(query(&q), vec!["java-sqli", "python-sqli"]);
+        let q = RuleQuery::new().id("python-*").mode(Mode::Search);
+        assert_eq!(query(&q), vec!["python-eval"]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let q =
+            RuleQuery::parse("severity:error mode:taint lang:python metadata.cwe:CWE-89").unwrap();
+        assert_eq!(
+            q,
+            RuleQuery::new()
+                .severity(Severity::Error)
+                .mode(Mode::Taint)
+                .language("python")
+                .metadata("cwe", "CWE-89")
+        );
+        assert_eq!(query(&q), vec!["python-sqli"]);
+
+        let q = RuleQuery::parse(r#"  lang:java,c  metadata.category:"best practice" "#).unwrap();
+        assert_eq!(query(&q), vec!["c-gets"]);
+
+        for invalid in [
+            "severity",
+            "severity:LOW",
+            "mode:fast",
+            "owner:me",
+            "metadata.:x",
+            "id:",
+            r#"id:"unterminated"#,
+        ] {
+            assert!(RuleQuery::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_query_rules() {
+        let ri = GenericRuleIndex::from_path_simple(QUERY_FILE).unwrap();
+        let rule_file = ri.query_rules(&RuleQuery::parse("id:*-sqli").unwrap());
+        let ids: Vec<&str> = rule_file
+            .rules
+            .iter()
+            .map(|r| r.get_id().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["java-sqli", "python-sqli"]);
+    }
+
+    #[test]
+    fn test_contains_word() {
+        assert!(contains_word("cwe-89: sql injection", "cwe-89"));
+        assert!(contains_word("cwe-895, cwe-89", "cwe-89"));
+        assert!(!contains_word("cwe-895", "cwe-89"));
+        assert!(!contains_word("xcwe-89", "cwe-89"));
+        // values that don't start or end with a letter or digit.
+        assert!(contains_word("cwe-89", "cwe-"));
+        assert!(contains_word("a03:2021", "a03:"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*c", "abbbc"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(glob_match("*.sqli*", "rules.python.sqli-format"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(!glob_match("a*c", "abd"));
+    }
+}
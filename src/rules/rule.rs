@@ -0,0 +1,807 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+use super::location::escape_pointer;
+use super::semgrep_generic_rule::GenericRule;
+use crate::{Error, Result};
+
+// Based on
+// https://github.com/returntocorp/semgrep-interfaces/blob/main/rule_schema_v1.yaml
+// (a copy is in source-schemas/rule_schema_v1.yaml).
+//
+// Pattern operators are externally tagged enums. They are (de)serialized as
+// single-key maps (e.g., `pattern-inside: ...`) using serde_yaml's
+// singleton_map_recursive instead of the default YAML tags (`!pattern-inside`).
+
+// ----- START Rule
+
+/// A typed Semgrep rule. Keys that are not modeled here are kept in `extra` and
+/// the original key order is kept in `key_order` so converting a GenericRule
+/// to a Rule and back returns the same rule with the keys in the same order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Rule {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<Mode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Mapping>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<Mapping>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paths: Option<Paths>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    // search mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub patterns: Option<Vec<PatternOperator>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub pattern_either: Option<Vec<PatternOperator>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_regex: Option<String>,
+
+    // taint mode.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub pattern_sources: Option<Vec<TaintSpec>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub pattern_propagators: Option<Vec<TaintSpec>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub pattern_sanitizers: Option<Vec<TaintSpec>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub pattern_sinks: Option<Vec<TaintSpec>>,
+
+    // join mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join: Option<Join>,
+
+    // extract mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dest_language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reduce: Option<String>,
+
+    // autofix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fix_regex: Option<FixRegex>,
+
+    /// everything else (e.g., `match`, `min-version`, experimental keys).
+    #[serde(flatten)]
+    pub extra: Mapping,
+
+    /// the order of the keys in the rule this Rule was created from. Empty
+    /// for rules created in code.
+    #[serde(skip)]
+    pub key_order: KeyOrder,
+}
+
+impl Rule {
+    /// convert a GenericRule to a Rule.
+    pub fn from_generic(rule: &GenericRule) -> Result<Rule> {
+        let value = Value::Mapping(rule.clone());
+        let mut typed: Rule = serde_yaml::from_value(value.clone())?;
+        typed.key_order = KeyOrder::from_value(&value);
+        Ok(typed)
+    }
+
+    /// convert the Rule back to a GenericRule. Keys are in the order of the
+    /// original rule, new keys are after them.
+    pub fn to_generic(&self) -> Result<GenericRule> {
+        match self.key_order.apply(serde_yaml::to_value(self)?, "") {
+            Value::Mapping(m) => Ok(m),
+            _ => Error::wrap_str("Rule was not serialized to a YAML mapping."),
+        }
+    }
+
+    /// deserialize a YAML string with one rule (not a rule file) into a Rule.
+    pub fn from_yaml(yaml: &str) -> Result<Rule> {
+        Rule::from_generic(&serde_yaml::from_str::<GenericRule>(yaml)?)
+    }
+
+    /// serialize the Rule to a YAML string.
+    pub fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(&self.to_generic()?).map_err(Error::from)
+    }
+}
+
+impl TryFrom<&GenericRule> for Rule {
+    type Error = Error;
+
+    fn try_from(rule: &GenericRule) -> Result<Rule> {
+        Rule::from_generic(rule)
+    }
+}
+
+impl TryFrom<&Rule> for GenericRule {
+    type Error = Error;
+
+    fn try_from(rule: &Rule) -> Result<GenericRule> {
+        rule.to_generic()
+    }
+}
+
+// ----- END Rule
+
+// ----- START KeyOrder
+
+/// the order of the keys in every mapping of a rule, keyed by the JSON pointer
+/// of the mapping (e.g., `/patterns/0/metavariable-pattern`). It is not part
+/// of the rule's value: all KeyOrders are equal so it doesn't change how rules
+/// are compared.
+#[derive(Clone, Debug, Default)]
+pub struct KeyOrder {
+    keys: HashMap<String, Vec<Value>>,
+}
+
+impl PartialEq for KeyOrder {
+    fn eq(&self, _: &KeyOrder) -> bool {
+        true
+    }
+}
+
+impl KeyOrder {
+    // record the order of the keys in every mapping in value.
+    fn from_value(value: &Value) -> KeyOrder {
+        let mut order = KeyOrder::default();
+        order.record(value, "");
+        order
+    }
+
+    fn record(&mut self, value: &Value, pointer: &str) {
+        match value {
+            Value::Mapping(map) => {
+                self.keys
+                    .insert(pointer.to_string(), map.keys().cloned().collect());
+                for (key, child) in map {
+                    self.record(child, &child_pointer(pointer, key));
+                }
+            }
+            Value::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.record(item, &format!("{}/{}", pointer, i));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // sort the keys of every mapping in value in the recorded order. Keys that
+    // were not recorded keep their order after the recorded ones.
+    fn apply(&self, value: Value, pointer: &str) -> Value {
+        match value {
+            Value::Mapping(map) => {
+                let mut map: Mapping = map
+                    .into_iter()
+                    .map(|(key, child)| {
+                        let child = self.apply(child, &child_pointer(pointer, &key));
+                        (key, child)
+                    })
+                    .collect();
+                let order = match self.keys.get(pointer) {
+                    Some(order) => order,
+                    None => return Value::Mapping(map),
+                };
+                let mut sorted = Mapping::new();
+                for key in order {
+                    if let Some(child) = map.shift_remove(key) {
+                        sorted.insert(key.clone(), child);
+                    }
+                }
+                sorted.extend(map);
+                Value::Mapping(sorted)
+            }
+            Value::Sequence(items) => Value::Sequence(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, item)| self.apply(item, &format!("{}/{}", pointer, i)))
+                    .collect(),
+            ),
+            v => v,
+        }
+    }
+}
+
+// the pointer of a value in a mapping. Keys that are not strings are not used
+// in rules and share the same pointer.
+fn child_pointer(pointer: &str, key: &Value) -> String {
+    format!(
+        "{}/{}",
+        pointer,
+        escape_pointer(key.as_str().unwrap_or("?"))
+    )
+}
+
+// ----- END KeyOrder
+
+/// values for the rule's `severity` key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+    // experimental.
+    Inventory,
+    Experiment,
+}
+
+impl Severity {
+    const ALL: [Severity; 5] = [
+        Severity::Info,
+        Severity::Warning,
+        Severity::Error,
+        Severity::Inventory,
+        Severity::Experiment,
+    ];
+
+    /// parse a severity ignoring case, e.g., `warning`. Use `str::parse` to
+    /// only accept the severity as it appears in the rule.
+    pub fn from_str_ignore_case(s: &str) -> Result<Severity> {
+        Severity::ALL
+            .into_iter()
+            .find(|sev| sev.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| unknown_severity(s))
+    }
+
+    /// return the severity as it appears in the rule.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "INFO",
+            Severity::Warning => "WARNING",
+            Severity::Error => "ERROR",
+            Severity::Inventory => "INVENTORY",
+            Severity::Experiment => "EXPERIMENT",
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = Error;
+
+    // parse the severity as it appears in the rule, e.g., `WARNING`.
+    fn from_str(s: &str) -> Result<Severity> {
+        Severity::ALL
+            .into_iter()
+            .find(|sev| sev.as_str() == s)
+            .ok_or_else(|| unknown_severity(s))
+    }
+}
+
+fn unknown_severity(s: &str) -> Error {
+    Error::new(format!("Unknown rule severity: `{}`.", s))
+}
+
+// language names from the `languages` definition in the rule schema. The
+// first name in each group is the canonical name and the others are aliases.
+pub(crate) const KNOWN_LANGUAGES: [&[&str]; 38] = [
+    &["apex"],
+    &["bash", "sh"],
+    &["c"],
+    &["clojure"],
+    &["cpp", "c++"],
+    &["csharp", "c#"],
+    &["dart"],
+    &["dockerfile", "docker"],
+    &["elixir", "ex"],
+    &["generic"],
+    &["go", "golang"],
+    &["hack"],
+    &["html"],
+    &["java"],
+    &["javascript", "js"],
+    &["json"],
+    &["jsonnet"],
+    &["julia"],
+    &["kotlin", "kt"],
+    &["lisp"],
+    &["lua"],
+    &["ocaml"],
+    &["php"],
+    &["python2"],
+    &["python3"],
+    &["python", "py"],
+    &["r"],
+    &["regex", "none"],
+    &["ruby"],
+    &["rust"],
+    &["scala"],
+    &["scheme"],
+    &["solidity", "sol"],
+    &["swift"],
+    &["terraform", "tf", "hcl"],
+    &["typescript", "ts"],
+    &["vue"],
+    &["yaml"],
+];
+
+// return true if name is a language or an alias. Names are case insensitive.
+pub(crate) fn is_known_language(name: &str) -> bool {
+    let name = name.to_lowercase();
+    KNOWN_LANGUAGES
+        .iter()
+        .any(|group| group.contains(&name.as_str()))
+}
+
+// return the canonical name of a language in lowercase, e.g., `python` for
+// `py` or `Python`. Unknown languages are returned in lowercase.
+pub(crate) fn canonical_language(name: &str) -> String {
+    let name = name.to_lowercase();
+    KNOWN_LANGUAGES
+        .iter()
+        .find(|group| group.contains(&name.as_str()))
+        .map(|group| group[0].to_string())
+        .unwrap_or(name)
+}
+
+/// values for the rule's `mode` key. Rules without a mode are search rules.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    #[default]
+    Search,
+    Taint,
+    Join,
+    Extract,
+}
+
+impl Mode {
+    const ALL: [Mode; 4] = [Mode::Search, Mode::Taint, Mode::Join, Mode::Extract];
+
+    /// parse a mode ignoring case, e.g., `Taint`. Use `str::parse` to only
+    /// accept the mode as it appears in the rule.
+    pub fn from_str_ignore_case(s: &str) -> Result<Mode> {
+        Mode::ALL
+            .into_iter()
+            .find(|m| m.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| unknown_mode(s))
+    }
+
+    /// return the mode as it appears in the rule.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Search => "search",
+            Mode::Taint => "taint",
+            Mode::Join => "join",
+            Mode::Extract => "extract",
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = Error;
+
+    // parse the mode as it appears in the rule, e.g., `taint`.
+    fn from_str(s: &str) -> Result<Mode> {
+        Mode::ALL
+            .into_iter()
+            .find(|m| m.as_str() == s)
+            .ok_or_else(|| unknown_mode(s))
+    }
+}
+
+fn unknown_mode(s: &str) -> Error {
+    Error::new(format!("Unknown rule mode: `{}`.", s))
+}
+
+/// the rule's `paths` key.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Paths {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Vec<String>>,
+}
+
+/// the rule's `fix-regex` key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixRegex {
+    pub regex: String,
+    pub replacement: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<i64>,
+}
+
+// ----- START pattern operators
+
+/// operators that can appear under `patterns` and `pattern-either`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PatternOperator {
+    Pattern(String),
+    PatternRegex(String),
+    PatternNotRegex(String),
+    Patterns(Vec<PatternOperator>),
+    PatternEither(Vec<PatternOperator>),
+    PatternInside(PatternClause),
+    PatternNotInside(PatternClause),
+    PatternNot(PatternClause),
+    FocusMetavariable(OneOrMany<String>),
+    MetavariableRegex(MetavariableRegex),
+    MetavariablePattern(MetavariablePattern),
+    MetavariableComparison(MetavariableComparison),
+    MetavariableAnalysis(MetavariableAnalysis),
+    /// deprecated.
+    PatternWherePython(String),
+}
+
+impl PatternOperator {
+    /// return the operator's key in the rule (e.g., `pattern-not-inside`).
+    pub fn key(&self) -> &'static str {
+        match self {
+            PatternOperator::Pattern(_) => "pattern",
+            PatternOperator::PatternRegex(_) => "pattern-regex",
+            PatternOperator::PatternNotRegex(_) => "pattern-not-regex",
+            PatternOperator::Patterns(_) => "patterns",
+            PatternOperator::PatternEither(_) => "pattern-either",
+            PatternOperator::PatternInside(_) => "pattern-inside",
+            PatternOperator::PatternNotInside(_) => "pattern-not-inside",
+            PatternOperator::PatternNot(_) => "pattern-not",
+            PatternOperator::FocusMetavariable(_) => "focus-metavariable",
+            PatternOperator::MetavariableRegex(_) => "metavariable-regex",
+            PatternOperator::MetavariablePattern(_) => "metavariable-pattern",
+            PatternOperator::MetavariableComparison(_) => "metavariable-comparison",
+            PatternOperator::MetavariableAnalysis(_) => "metavariable-analysis",
+            PatternOperator::PatternWherePython(_) => "pattern-where-python",
+        }
+    }
+}
+
+/// value of `pattern-inside`, `pattern-not-inside` and `pattern-not`. Either a
+/// pattern string or a nested operator (e.g., `patterns`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PatternClause {
+    Pattern(String),
+    Operator(Box<PatternOperator>),
+}
+
+/// a value that can be a single item or a list (e.g., `focus-metavariable`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MetavariableRegex {
+    pub metavariable: String,
+    pub regex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constant_propagation: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MetavariablePattern {
+    pub metavariable: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patterns: Option<Vec<PatternOperator>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_either: Option<Vec<PatternOperator>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MetavariableComparison {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metavariable: Option<String>,
+    pub comparison: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MetavariableAnalysis {
+    pub analyzer: String,
+    pub metavariable: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<Mapping>,
+}
+
+// ----- END pattern operators
+
+// ----- START taint and join
+
+/// an item in `pattern-sources`, `pattern-propagators`, `pattern-sanitizers`
+/// or `pattern-sinks`. Keys such as `label`, `requires`, `by-side-effect` or
+/// the propagator's `from` and `to` are kept in `extra`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaintSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patterns: Option<Vec<PatternOperator>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern_either: Option<Vec<PatternOperator>>,
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+/// the rule's `join` key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Join {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refs: Option<Vec<JoinRef>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Vec<Rule>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on: Option<Vec<String>>,
+}
+
+/// a reference to another rule in `join.refs`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JoinRef {
+    pub rule: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renames: Option<Vec<JoinRename>>,
+    #[serde(default, rename = "as", skip_serializing_if = "Option::is_none")]
+    pub as_: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JoinRename {
+    pub from: String,
+    pub to: String,
+}
+
+// ----- END taint and join
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GenericRuleFile;
+
+    const CONTROL_FILES: [&str; 8] = [
+        "tests/rules/cpp/arrays-out-of-bounds-access.yaml",
+        "tests/rules/cpp/arrays-passed-to-functions.yaml",
+        "tests/rules/cpp/encode-decode-function-name.yaml",
+        "tests/rules/cpp/encrypt-decrypt-function-name.yaml",
+        "tests/rules/cpp/memcpy-insecure-use.yaml",
+        "tests/rules/cpp/potentially-uninitialized-pointer.yaml",
+        "tests/rules/cpp/snprintf-insecure-use.yaml",
+        "tests/rules/multiple-rules.yaml",
+    ];
+
+    // the keys of every mapping in value, in order.
+    fn keys(value: &Value) -> Vec<String> {
+        let mut results = Vec::new();
+        match value {
+            Value::Mapping(map) => {
+                for (key, child) in map {
+                    results.push(key.as_str().unwrap_or("?").to_string());
+                    results.extend(keys(child));
+                }
+            }
+            Value::Sequence(items) => items.iter().for_each(|i| results.extend(keys(i))),
+            _ => {}
+        }
+        results
+    }
+
+    // GenericRule -> Rule -> GenericRule should return the original rule.
+    // Mapping equality ignores the key order so check it separately.
+    #[test]
+    fn test_round_trip() {
+        for file in CONTROL_FILES {
+            let rule_file = GenericRuleFile::from_file(file).unwrap();
+            for generic in &rule_file.rules {
+                let rule = Rule::from_generic(generic).unwrap();
+                let converted = rule.to_generic().unwrap();
+                assert_eq!(&converted, generic, "{}", file);
+                assert_eq!(
+                    keys(&Value::Mapping(converted)),
+                    keys(&Value::Mapping(generic.clone())),
+                    "{}",
+                    file
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_key_order() {
+        // the keys are not in the order of the Rule fields.
+        let yaml = r#"
+patterns:
+  - metavariable-regex:
+      regex: ^foo
+      metavariable: $X
+  - pattern: foo($X)
+languages: [python]
+x-custom: 1
+id: key-order
+message: hi
+severity: INFO
+"#;
+        let rule = Rule::from_yaml(yaml).unwrap();
+        let generic = rule.to_generic().unwrap();
+        assert_eq!(
+            keys(&Value::Mapping(generic)),
+            vec![
+                "patterns",
+                "metavariable-regex",
+                "regex",
+                "metavariable",
+                "pattern",
+                "languages",
+                "x-custom",
+                "id",
+                "message",
+                "severity"
+            ]
+        );
+        assert!(rule.to_yaml().unwrap().starts_with("patterns:"));
+
+        // new keys are after the original ones.
+        let mut rule = rule;
+        rule.fix = Some("bar($X)".to_string());
+        let generic = rule.to_generic().unwrap();
+        assert_eq!(generic.keys().last().unwrap().as_str(), Some("fix"));
+    }
+
+    #[test]
+    fn test_typed_fields() {
+        let rule_file = GenericRuleFile::from_file("tests/rules/multiple-rules.yaml").unwrap();
+        let rule = Rule::from_generic(&rule_file.rules[1]).unwrap();
+
+        assert_eq!(rule.id, "potentially-uninitialized-pointer");
+        assert_eq!(rule.severity, Some(Severity::Info));
+        assert_eq!(
+            rule.languages,
+            Some(vec!["cpp".to_string(), "c".to_string()])
+        );
+
+        let patterns = rule.patterns.unwrap();
+        assert!(matches!(patterns[0], PatternOperator::Pattern(_)));
+        assert!(matches!(
+            patterns[1],
+            PatternOperator::PatternNotInside(PatternClause::Pattern(_))
+        ));
+        assert_eq!(patterns[1].key(), "pattern-not-inside");
+    }
+
+    // unknown keys are kept in extra and written back.
+    #[test]
+    fn test_unknown_keys() {
+        let yaml = r#"
+id: taint-rule
+mode: taint
+message: tainted
+severity: ERROR
+languages: [python]
+min-version: 1.0.0
+pattern-sources:
+  - pattern: source()
+    label: USER
+pattern-sinks:
+  - patterns:
+      - pattern-inside: |
+          sink($X)
+      - focus-metavariable: $X
+    requires: USER
+"#;
+        let rule = Rule::from_yaml(yaml).unwrap();
+        assert_eq!(rule.mode, Some(Mode::Taint));
+        assert_eq!(rule.extra.get("min-version").unwrap(), "1.0.0");
+
+        let sinks = rule.pattern_sinks.as_ref().unwrap();
+        assert_eq!(sinks[0].extra.get("requires").unwrap(), "USER");
+        assert!(matches!(
+            sinks[0].patterns.as_ref().unwrap()[1],
+            PatternOperator::FocusMetavariable(OneOrMany::One(_))
+        ));
+
+        let generic = rule.to_generic().unwrap();
+        let original: GenericRule = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(generic, original);
+    }
+
+    #[test]
+    fn test_nested_clause() {
+        let yaml = r#"
+id: nested
+message: nested
+severity: WARNING
+languages: [go]
+patterns:
+  - pattern: foo($X)
+  - pattern-not-inside:
+      patterns:
+        - pattern-inside: bar(...)
+        - pattern: foo(1)
+  - metavariable-pattern:
+      metavariable: $X
+      pattern-either:
+        - pattern: a
+        - pattern: b
+"#;
+        let rule = Rule::from_yaml(yaml).unwrap();
+        let patterns = rule.patterns.as_ref().unwrap();
+        match &patterns[1] {
+            PatternOperator::PatternNotInside(PatternClause::Operator(op)) => {
+                assert!(matches!(**op, PatternOperator::Patterns(_)))
+            }
+            _ => panic!("expected a nested pattern-not-inside"),
+        }
+
+        let original: GenericRule = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(rule.to_generic().unwrap(), original);
+    }
+
+    #[test]
+    fn test_severity_and_mode_from_str() {
+        assert_eq!("WARNING".parse::<Severity>().unwrap(), Severity::Warning);
+        assert!("warning".parse::<Severity>().is_err());
+        assert_eq!(
+            Severity::from_str_ignore_case("warning").unwrap(),
+            Severity::Warning
+        );
+        assert!(Severity::from_str_ignore_case("LOW").is_err());
+
+        assert_eq!("taint".parse::<Mode>().unwrap(), Mode::Taint);
+        assert!("Taint".parse::<Mode>().is_err());
+        assert_eq!(Mode::from_str_ignore_case("Taint").unwrap(), Mode::Taint);
+        assert!(Mode::from_str_ignore_case("fast").is_err());
+    }
+}
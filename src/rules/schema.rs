@@ -0,0 +1,549 @@
+// Validate rules against the Semgrep rule schema in
+// source-schemas/rule_schema_v1.yaml.
+//
+// The schema is a JSON schema (draft-07) written in YAML. This is a small
+// validator that supports the keywords used in that file. It's not a complete
+// JSON schema implementation.
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use super::location::{escape_pointer, YamlLocations};
+use super::semgrep_generic_rule::GenericRuleFile;
+use crate::{Error, Result};
+
+const RULE_SCHEMA: &str = include_str!("../../source-schemas/rule_schema_v1.yaml");
+
+// ----- START SchemaViolation
+
+/// a rule that doesn't match the schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// ID of the rule, None if the rule doesn't have one or the violation is
+    /// not inside a rule.
+    pub rule_id: Option<String>,
+    /// JSON pointer to the offending node, e.g., `/rules/0/patterns/1`.
+    pub path: String,
+    /// line of the node in the source (starts at 1). None if the rule file
+    /// was not created from a YAML string.
+    pub line: Option<usize>,
+    /// column of the node in the source (starts at 1).
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(id) = &self.rule_id {
+            write!(f, "{}: ", id)?;
+        }
+        write!(f, "{}", self.path)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, " (line {}, column {})", line, column)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+// ----- END SchemaViolation
+
+impl GenericRuleFile {
+    /// validate the rule file against the rule schema and return all
+    /// violations. An empty vector means the file is valid. Line and column
+    /// are not available because the file has already been deserialized, use
+    /// `validate_yaml_schema` if you have the YAML string.
+    pub fn validate_schema(&self) -> Vec<SchemaViolation> {
+        validate(self, None)
+    }
+
+    /// deserialize a YAML string into a GenericRuleFile and validate it
+    /// against the rule schema. Violations include the line and column.
+    /// Returns an error if the string cannot be deserialized.
+    pub fn validate_yaml_schema(yaml: &str) -> Result<Vec<SchemaViolation>> {
+        let rule_file = GenericRuleFile::from_yaml(yaml)?;
+        let locations = YamlLocations::from_yaml(yaml);
+        Ok(validate(&rule_file, Some(&locations)))
+    }
+}
+
+fn validate(
+    rule_file: &GenericRuleFile,
+    locations: Option<&YamlLocations>,
+) -> Vec<SchemaViolation> {
+    let instance = match serde_json::to_value(rule_file) {
+        Ok(v) => v,
+        Err(e) => {
+            return vec![SchemaViolation {
+                rule_id: None,
+                path: String::new(),
+                line: None,
+                column: None,
+                message: format!("cannot convert the rules to JSON: {}", e),
+            }]
+        }
+    };
+
+    let schema = rule_schema();
+    let validator = Validator { root: &schema };
+    let mut errors: Vec<(String, String)> = Vec::new();
+    validator.validate(&schema, &instance, "", &mut errors);
+
+    errors
+        .into_iter()
+        .map(|(path, message)| {
+            let position = locations.and_then(|l| l.get(&path));
+            SchemaViolation {
+                rule_id: rule_id_for_path(&instance, &path),
+                line: position.map(|p| p.line),
+                column: position.map(|p| p.column),
+                path,
+                message,
+            }
+        })
+        .collect()
+}
+
+// deserialize the bundled rule schema.
+fn rule_schema() -> Value {
+    serde_yaml::from_str::<Value>(RULE_SCHEMA).expect("the bundled rule schema is invalid")
+}
+
+// return the ID of the rule that contains the node at `path`.
+fn rule_id_for_path(instance: &Value, path: &str) -> Option<String> {
+    let mut parts = path.split('/').skip(1);
+    match (parts.next(), parts.next()) {
+        (Some("rules"), Some(index)) => instance
+            .pointer(&format!("/rules/{}/id", index))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        _ => None,
+    }
+}
+
+// ----- START Validator
+
+struct Validator<'a> {
+    root: &'a Value,
+}
+
+impl<'a> Validator<'a> {
+    // return true if `instance` is valid.
+    fn is_valid(&self, schema: &Value, instance: &Value) -> bool {
+        let mut errors = Vec::new();
+        self.validate(schema, instance, "", &mut errors);
+        errors.is_empty()
+    }
+
+    // validate `instance` against `schema` and add any errors to `errors`.
+    // Each error is (JSON pointer, message).
+    fn validate(
+        &self,
+        schema: &Value,
+        instance: &Value,
+        path: &str,
+        errors: &mut Vec<(String, String)>,
+    ) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                errors.push((
+                    path.to_string(),
+                    format!("`{}` is not allowed here", last_key(path)),
+                ));
+                return;
+            }
+            Value::Object(s) => s,
+            _ => return,
+        };
+
+        // in draft-07, keywords next to $ref are ignored.
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            match self.resolve(reference) {
+                Some(target) => self.validate(target, instance, path, errors),
+                None => errors.push((path.to_string(), format!("cannot resolve {}", reference))),
+            }
+            return;
+        }
+
+        if let Some(expected) = schema.get("type").and_then(|t| t.as_str()) {
+            if !has_type(instance, expected) {
+                errors.push((
+                    path.to_string(),
+                    format!("expected {}, found {}", expected, type_name(instance)),
+                ));
+                // the rest of the keywords don't make sense for the wrong type.
+                return;
+            }
+        }
+
+        if let Some(Value::Array(values)) = schema.get("enum") {
+            if !values.contains(instance) {
+                errors.push((
+                    path.to_string(),
+                    format!("{} is not one of: {}", short(instance), join_values(values)),
+                ));
+            }
+        }
+
+        if let Some(value) = schema.get("const") {
+            if value != instance {
+                errors.push((path.to_string(), format!("expected {}", short(value))));
+            }
+        }
+
+        if let Value::Object(object) = instance {
+            self.validate_object(schema, object, path, errors);
+        }
+
+        if let Value::Array(items) = instance {
+            if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()) {
+                if (items.len() as u64) < min {
+                    errors.push((
+                        path.to_string(),
+                        format!("expected at least {} item(s)", min),
+                    ));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    self.validate(item_schema, item, &format!("{}/{}", path, i), errors);
+                }
+            }
+        }
+
+        if let Some(Value::Array(all)) = schema.get("allOf") {
+            for s in all {
+                self.validate(s, instance, path, errors);
+            }
+        }
+
+        if let Some(Value::Array(any)) = schema.get("anyOf") {
+            self.validate_alternatives(any, instance, path, false, errors);
+        }
+
+        if let Some(Value::Array(one)) = schema.get("oneOf") {
+            self.validate_alternatives(one, instance, path, true, errors);
+        }
+
+        if let Some(not) = schema.get("not") {
+            if self.is_valid(not, instance) {
+                let keys = required_keys(self.deref(not));
+                let message = match keys.is_empty() {
+                    true => "matches a schema that is not allowed".to_string(),
+                    false => format!("cannot be used with {}", join_keys(&keys)),
+                };
+                errors.push((path.to_string(), message));
+            }
+        }
+
+        if let Some(condition) = schema.get("if") {
+            let branch = match self.is_valid(condition, instance) {
+                true => schema.get("then"),
+                false => schema.get("else"),
+            };
+            if let Some(b) = branch {
+                self.validate(b, instance, path, errors);
+            }
+        }
+    }
+
+    fn validate_object(
+        &self,
+        schema: &Map<String, Value>,
+        object: &Map<String, Value>,
+        path: &str,
+        errors: &mut Vec<(String, String)>,
+    ) {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !object.contains_key(key) {
+                    errors.push((path.to_string(), format!("missing required key `{}`", key)));
+                }
+            }
+        }
+
+        let properties = schema.get("properties").and_then(|p| p.as_object());
+        for (key, value) in object {
+            let child = format!("{}/{}", path, escape_pointer(key));
+            match properties.and_then(|p| p.get(key)) {
+                Some(property) => self.validate(property, value, &child, errors),
+                None => {
+                    if let Some(Value::Bool(false)) = schema.get("additionalProperties") {
+                        errors.push((child, format!("unknown key `{}`", key)));
+                    }
+                }
+            }
+        }
+    }
+
+    // validate anyOf (exactly_one = false) and oneOf (exactly_one = true).
+    fn validate_alternatives(
+        &self,
+        alternatives: &[Value],
+        instance: &Value,
+        path: &str,
+        exactly_one: bool,
+        errors: &mut Vec<(String, String)>,
+    ) {
+        let mut results: Vec<Vec<(String, String)>> = Vec::new();
+        for alternative in alternatives {
+            let mut errs = Vec::new();
+            self.validate(alternative, instance, path, &mut errs);
+            results.push(errs);
+        }
+
+        let valid = results.iter().filter(|r| r.is_empty()).count();
+        if valid == 1 || (valid > 1 && !exactly_one) {
+            return;
+        }
+        if valid > 1 {
+            let keys: Vec<String> = alternatives
+                .iter()
+                .flat_map(|a| required_keys(self.deref(a)))
+                .filter(|k| instance.get(k).is_some())
+                .collect();
+            let message = match keys.is_empty() {
+                true => "matches more than one alternative".to_string(),
+                false => format!("only one of {} is allowed", join_keys(&keys)),
+            };
+            errors.push((path.to_string(), message));
+            return;
+        }
+
+        // nothing matched. If one alternative got further into the instance
+        // than the rest, its errors are the most useful. Otherwise, summarize.
+        let depth = |errs: &Vec<(String, String)>| {
+            errs.iter()
+                .map(|(p, _)| p.matches('/').count())
+                .max()
+                .unwrap_or(0)
+        };
+        let here = path.matches('/').count();
+        let deepest = results.iter().map(depth).max().unwrap_or(0);
+        let candidates: Vec<&Vec<(String, String)>> =
+            results.iter().filter(|r| depth(r) == deepest).collect();
+
+        if deepest > here && candidates.len() == 1 {
+            errors.extend(candidates[0].iter().cloned());
+            return;
+        }
+
+        let keys: Vec<String> = alternatives
+            .iter()
+            .flat_map(|a| required_keys(self.deref(a)))
+            .collect();
+        let message = match (keys.is_empty(), instance.is_object()) {
+            (false, true) => format!("expected one of {}", join_keys(&keys)),
+            _ => match results.iter().min_by_key(|r| r.len()) {
+                Some(best) if !best.is_empty() => best[0].1.clone(),
+                _ => "does not match any of the allowed alternatives".to_string(),
+            },
+        };
+        errors.push((path.to_string(), message));
+    }
+
+    // follow $ref (if any) and return the schema.
+    fn deref<'b>(&'b self, schema: &'b Value) -> &'b Value {
+        match schema.get("$ref").and_then(|r| r.as_str()) {
+            Some(reference) => self.resolve(reference).unwrap_or(schema),
+            None => schema,
+        }
+    }
+
+    // resolve a local reference such as `#/$defs/pattern`.
+    fn resolve(&self, reference: &str) -> Option<&'a Value> {
+        reference
+            .strip_prefix('#')
+            .and_then(|pointer| self.root.pointer(pointer))
+    }
+}
+
+// ----- END Validator
+
+// return the keys in the `required` keyword of a schema.
+fn required_keys(schema: &Value) -> Vec<String> {
+    match schema.get("required") {
+        Some(Value::Array(keys)) => keys
+            .iter()
+            .filter_map(|k| k.as_str())
+            .map(|k| k.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn has_type(instance: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => instance.is_object(),
+        "array" => instance.is_array(),
+        "string" => instance.is_string(),
+        "boolean" => instance.is_boolean(),
+        "integer" => instance.is_i64() || instance.is_u64(),
+        "number" => instance.is_number(),
+        "null" => instance.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// return a short representation of a value for error messages.
+fn short(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("`{}`", s),
+        Value::Array(_) | Value::Object(_) => type_name(value).to_string(),
+        v => format!("`{}`", v),
+    }
+}
+
+fn join_values(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(|v| match v {
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn join_keys(keys: &[String]) -> String {
+    let mut unique: Vec<&String> = Vec::new();
+    for k in keys {
+        if !unique.contains(&k) {
+            unique.push(k);
+        }
+    }
+    unique
+        .iter()
+        .map(|k| format!("`{}`", k))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// return the last segment of a JSON pointer.
+fn last_key(path: &str) -> String {
+    path.rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .replace("~1", "/")
+        .replace("~0", "~")
+}
+
+/// validate a YAML string with rules and return an error with all the
+/// violations if it doesn't match the schema.
+pub(crate) fn check_yaml(yaml: &str) -> Result<()> {
+    let violations = GenericRuleFile::validate_yaml_schema(yaml)?;
+    match violations.is_empty() {
+        true => Ok(()),
+        false => Err(Error::SchemaError(violations)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTROL_FILES: [&str; 8] = [
+        "tests/rules/cpp/arrays-out-of-bounds-access.yaml",
+        "tests/rules/cpp/arrays-passed-to-functions.yaml",
+        "tests/rules/cpp/encode-decode-function-name.yaml",
+        "tests/rules/cpp/encrypt-decrypt-function-name.yaml",
+        "tests/rules/cpp/memcpy-insecure-use.yaml",
+        "tests/rules/cpp/potentially-uninitialized-pointer.yaml",
+        "tests/rules/cpp/snprintf-insecure-use.yaml",
+        "tests/rules/multiple-rules.yaml",
+    ];
+
+    #[test]
+    fn test_valid_rules() {
+        for file in CONTROL_FILES {
+            let rule_file = GenericRuleFile::from_file(file).unwrap();
+            assert_eq!(rule_file.validate_schema(), vec![], "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_missing_message() {
+        let yaml = "rules:\n- id: no-message\n  pattern: foo()\n  languages: [python]\n  severity: ERROR\n";
+        let violations = GenericRuleFile::validate_yaml_schema(yaml).unwrap();
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].rule_id.as_deref(), Some("no-message"));
+        assert_eq!(violations[0].path, "/rules/0");
+        assert_eq!(violations[0].line, Some(2));
+        assert_eq!(violations[0].column, Some(3));
+        assert_eq!(violations[0].message, "missing required key `message`");
+    }
+
+    #[test]
+    fn test_misspelled_operator() {
+        let yaml = "rules:\n- id: typo\n  message: typo\n  languages: [python]\n  severity: ERROR\n  patterns:\n    - patern: foo()\n";
+        let violations = GenericRuleFile::validate_yaml_schema(yaml).unwrap();
+
+        let typo = violations
+            .iter()
+            .find(|v| v.path == "/rules/0/patterns/0")
+            .unwrap();
+        assert_eq!(typo.line, Some(7));
+        assert!(
+            typo.message.contains("`pattern-inside`"),
+            "{}",
+            typo.message
+        );
+
+        // unknown top-level keys.
+        let yaml = "rules:\n- id: typo\n  message: typo\n  languages: [python]\n  severity: ERROR\n  patern: foo()\n";
+        let violations = GenericRuleFile::validate_yaml_schema(yaml).unwrap();
+        assert!(violations.iter().any(|v| v.path == "/rules/0/patern"
+            && v.message == "unknown key `patern`"
+            && v.line == Some(6)));
+    }
+
+    #[test]
+    fn test_wrong_severity() {
+        let yaml = "rules:\n- id: sev\n  message: sev\n  languages: [python]\n  severity: LOW\n  pattern: foo()\n";
+        let violations = GenericRuleFile::validate_yaml_schema(yaml).unwrap();
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "/rules/0/severity");
+        assert_eq!(violations[0].line, Some(5));
+        assert!(violations[0].message.starts_with("`LOW` is not one of"));
+    }
+
+    #[test]
+    fn test_taint_rule() {
+        let yaml = r#"
+rules:
+- id: taint
+  mode: taint
+  message: taint
+  languages: [python]
+  severity: ERROR
+  pattern-sources:
+    - pattern: source()
+  pattern-sinks:
+    - patterns:
+        - pattern-inside: sink($X)
+        - focus-metavariable: $X
+"#;
+        assert_eq!(check_yaml(yaml).unwrap(), ());
+
+        // taint rules cannot have a `pattern`.
+        let bad = yaml.replace("  mode: taint\n", "  mode: taint\n  pattern: foo()\n");
+        let violations = GenericRuleFile::validate_yaml_schema(&bad).unwrap();
+        assert!(violations
+            .iter()
+            .any(|v| v.path == "/rules/0/pattern" && v.line == Some(5)));
+    }
+}
@@ -1,8 +1,18 @@
 use log::error;
-use std::{collections::HashMap, path::Path, vec};
+use std::{
+    collections::HashMap,
+    path::{Component, Path},
+    vec,
+};
 
 use serde::{Deserialize, Serialize};
 
+use serde_yaml::{Mapping, Value};
+
+use super::hash::content_hash;
+use super::patterns::{find_patterns, find_rule_metavariables, PatternEntry};
+use super::rule::{Mode, Paths, Rule, Severity};
+use super::template::{expand_template, is_template, DEFAULT_MAX_TEMPLATE_RULES};
 use crate::{utils::read_file_to_string, Error, Result};
 
 const RULE_SEPARATOR: &str = ".";
@@ -17,6 +27,25 @@ pub trait GenericRuleExt {
     fn to_string(&self) -> Result<String>;
 }
 
+/// accessors for the fields of a GenericRule. This is a separate trait from
+/// GenericRuleExt so types that implement GenericRuleExt don't have to
+/// implement these.
+pub trait GenericRuleFieldsExt {
+    fn to_rule(&self) -> Result<Rule>;
+    fn get_mode(&self) -> Result<Mode>;
+    fn get_languages(&self) -> Result<Vec<&str>>;
+    fn get_severity(&self) -> Result<Severity>;
+    fn get_message(&self) -> Result<&str>;
+    fn get_metadata(&self) -> Result<&Mapping>;
+    fn get_paths(&self) -> Result<Paths>;
+    fn get_options(&self) -> Result<&Mapping>;
+    fn get_fix(&self) -> Result<&str>;
+    fn content_hash(&self) -> String;
+    fn get_patterns(&self) -> Vec<PatternEntry>;
+    fn get_metavariables(&self) -> Vec<String>;
+    fn is_template(&self) -> bool;
+}
+
 impl GenericRuleExt for GenericRule {
     fn get_id(&self) -> Result<&str> {
         // original
@@ -54,10 +83,183 @@ impl GenericRuleExt for GenericRule {
     }
 }
 
+impl GenericRuleFieldsExt for GenericRule {
+    // convert the GenericRule to a typed Rule.
+    fn to_rule(&self) -> Result<Rule> {
+        Rule::from_generic(self)
+    }
+
+    // return the rule's mode. If the rule doesn't have a `mode` field, the
+    // mode is detected from the other fields and defaults to search.
+    fn get_mode(&self) -> Result<Mode> {
+        match self.get("mode") {
+            Some(mode) => mode
+                .as_str()
+                .ok_or_else(|| wrong_type("mode", "a string"))?
+                .parse::<Mode>(),
+            None if self.contains_key("pattern-sources") => Ok(Mode::Taint),
+            None if self.contains_key("join") => Ok(Mode::Join),
+            None if self.contains_key("extract") => Ok(Mode::Extract),
+            None => Ok(Mode::Search),
+        }
+    }
+
+    // return the rule's languages.
+    fn get_languages(&self) -> Result<Vec<&str>> {
+        get_field(self, "languages")?
+            .as_sequence()
+            .ok_or_else(|| wrong_type("languages", "a list"))?
+            .iter()
+            .map(|l| {
+                l.as_str()
+                    .ok_or_else(|| wrong_type("languages", "a list of strings"))
+            })
+            .collect()
+    }
+
+    // return the rule's severity.
+    fn get_severity(&self) -> Result<Severity> {
+        get_field(self, "severity")?
+            .as_str()
+            .ok_or_else(|| wrong_type("severity", "a string"))?
+            .parse::<Severity>()
+    }
+
+    // return the rule's message.
+    fn get_message(&self) -> Result<&str> {
+        get_field(self, "message")?
+            .as_str()
+            .ok_or_else(|| wrong_type("message", "a string"))
+    }
+
+    // return the rule's metadata.
+    fn get_metadata(&self) -> Result<&Mapping> {
+        get_field(self, "metadata")?
+            .as_mapping()
+            .ok_or_else(|| wrong_type("metadata", "a mapping"))
+    }
+
+    // return the rule's `paths` with the include and exclude lists.
+    fn get_paths(&self) -> Result<Paths> {
+        let paths = get_field(self, "paths")?;
+        if !paths.is_mapping() {
+            return Err(wrong_type("paths", "a mapping"));
+        }
+        serde_yaml::from_value::<Paths>(paths.clone())
+            .map_err(|e| Error::new(format!("Cannot convert rule's `paths` field: {}.", e)))
+    }
+
+    // return the rule's options.
+    fn get_options(&self) -> Result<&Mapping> {
+        get_field(self, "options")?
+            .as_mapping()
+            .ok_or_else(|| wrong_type("options", "a mapping"))
+    }
+
+    // return the rule's fix.
+    fn get_fix(&self) -> Result<&str> {
+        get_field(self, "fix")?
+            .as_str()
+            .ok_or_else(|| wrong_type("fix", "a string"))
+    }
+
+    // return a hash of the rule's contents (hex encoded SHA-256). Key order,
+    // formatting and comments don't change the hash.
+    fn content_hash(&self) -> String {
+        content_hash(self)
+    }
+
+    // return every pattern in the rule with its operator, path and
+    // metavariables, including the ones in taint specs, metavariable-pattern
+    // and join rules.
+    fn get_patterns(&self) -> Vec<PatternEntry> {
+        find_patterns(self)
+    }
+
+    // return the metavariables bound by the rule's patterns.
+    fn get_metavariables(&self) -> Vec<String> {
+        find_rule_metavariables(self)
+    }
+
+    // return true if the rule is a template that expands into other rules.
+    fn is_template(&self) -> bool {
+        is_template(self)
+    }
+}
+
+// return the value of a field in the rule or an error if it doesn't exist.
+fn get_field<'a>(rule: &'a GenericRule, field: &str) -> Result<&'a Value> {
+    rule.get(field)
+        .ok_or_else(|| Error::new(format!("The rule doesn't have a `{}` field.", field)))
+}
+
+// return an error for a field with the wrong type.
+fn wrong_type(field: &str, expected: &str) -> Error {
+    Error::new(format!("The rule's `{}` field is not {}.", field, expected))
+}
+
+// return the key of a rule in the index. See GenericRuleFile::create_index.
+// Returns None if the rule doesn't have an ID.
+pub(crate) fn index_key(
+    rule: &GenericRule,
+    path: &str,
+    root: Option<&str>,
+    complete: bool,
+) -> Option<String> {
+    match rule.get_id() {
+        Err(e) => {
+            // log the error and continue.
+            error!("error getting rule's id: {}", e);
+            None
+        }
+        // create the complete rule ID.
+        Ok(id) if complete => Some(qualified_rule_id(path, root, id)),
+        // just use the rule ID from the file.
+        Ok(id) => Some(id.to_string()),
+    }
+}
+
+/// return the fully qualified ID that Semgrep reports for a rule (e.g., in
+/// `CliMatch.check_id`). The ID is the path of the rule file relative to
+/// `root` without the extension and with the path separators replaced by `.`,
+/// followed by the rule ID. E.g., the rule `buffer-overflow` in
+/// `rules/cpp/security/buffer-overflow.yaml` with root `rules` is
+/// `cpp.security.buffer-overflow.buffer-overflow`.
+///
+/// If the file is not in root (or root is None), the whole path is used.
+/// Prefixes such as `/` and `./` are ignored.
+pub fn qualified_rule_id(file: &str, root: Option<&str>, rule_id: &str) -> String {
+    let path = Path::new(file);
+    // 1. make the path relative to root.
+    let relative = root.and_then(|r| path.strip_prefix(r).ok()).unwrap_or(path);
+    // 2. remove the extension (if any).
+    let relative = relative.with_extension("");
+    // 3. replace the path separator with `.`.
+    let mut parts: Vec<String> = relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    parts.push(rule_id.to_string());
+    parts.join(RULE_SEPARATOR)
+}
+
+// return true if Semgrep's check_id is for the rule. Semgrep prefixes the rule
+// ID with the path of the rule file (e.g., `tmp.tmpabc123.rule-id`).
+pub(crate) fn matches_check_id(rule_id: &str, check_id: &str) -> bool {
+    check_id == rule_id
+        || check_id
+            .strip_suffix(rule_id)
+            .map(|prefix| prefix.ends_with('.'))
+            .unwrap_or(false)
+}
+
 // ----- END GenericRule
 
 // This allows us to split the rules without caring about their contents.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GenericRuleFile {
     pub rules: Vec<GenericRule>,
 }
@@ -78,44 +280,31 @@ impl GenericRuleFile {
     //
     // If `complete` it true, this function uses the same ID that Semgrep uses which
     // contains the path followed by the rule ID in the file. E.g., if the
-    // /rules/cpp/security/buffer-overflow.cpp file contains the rule with ID
+    // rules/cpp/security/buffer-overflow.yaml file contains the rule with ID
     // buffer-overflow, the complete ruleID will be
-    // rules.cpp/security.buffer-overflow.buffer-overflow. Hence, rule ID is very
-    // much dependent on the path of the registry passed to the server.
+    // rules.cpp.security.buffer-overflow.buffer-overflow. Hence, rule ID is very
+    // much dependent on the path of the registry passed to the server. Use
+    // create_index_with_root to make the IDs relative to the registry.
     //
     // If `complete` is false, just the rule ID from the file will be used.
     pub fn create_index(&self, path: &str, complete: bool) -> HashMap<String, GenericRule> {
+        self.create_index_with_root(path, None, complete)
+    }
+
+    // same as create_index but complete IDs are relative to root. See
+    // qualified_rule_id.
+    pub fn create_index_with_root(
+        &self,
+        path: &str,
+        root: Option<&str>,
+        complete: bool,
+    ) -> HashMap<String, GenericRule> {
         let mut index: HashMap<String, GenericRule> = HashMap::new();
 
         for rule in &self.rules {
-            let mut path_string: String;
-
-            match complete {
-                true => {
-                    // create the complete rule ID.
-                    path_string = Path::new(path)
-                        // 1. remove the extension (if any)
-                        .with_extension("")
-                        .to_string_lossy()
-                        .to_string()
-                        // 2. replace the path separator with `.`.
-                        .replace(std::path::MAIN_SEPARATOR, RULE_SEPARATOR);
-                    path_string.push_str(RULE_SEPARATOR);
-                }
-                false => {
-                    let id = match rule.get_id() {
-                        Err(e) => {
-                            // log the error and continue.
-                            error!("error getting rule's id: {}", e.to_string());
-                            continue;
-                        }
-                        Ok(i) => i,
-                    };
-                    // just use the rule ID from the file.
-                    path_string = id.to_string();
-                }
+            if let Some(key) = index_key(rule, path, root, complete) {
+                index.insert(key, rule.to_owned());
             }
-            index.insert(path_string, rule.to_owned());
         }
         index
     }
@@ -134,6 +323,18 @@ impl GenericRuleFile {
         serde_yaml::from_str::<GenericRuleFile>(&content).map_err(Error::from)
     }
 
+    // return a GenericRuleFile where every rule template (a rule with a
+    // `template` field) is replaced by the rules it expands to. See
+    // rules/template.rs for the format. A template can expand to at most
+    // 1000 rules.
+    pub fn expand_templates(&self) -> Result<GenericRuleFile> {
+        let mut rules: Vec<GenericRule> = Vec::new();
+        for rule in &self.rules {
+            rules.extend(expand_template(rule, DEFAULT_MAX_TEMPLATE_RULES)?);
+        }
+        Ok(GenericRuleFile { rules })
+    }
+
     // serialize a GenericRuleFile to a YAML string.
     pub fn to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(Error::from)
@@ -150,8 +351,10 @@ impl GenericRuleFile {
 
 #[cfg(test)]
 mod tests {
-    use crate::GenericRuleExt;
+    use super::{matches_check_id, qualified_rule_id};
     use crate::GenericRuleFile;
+    use crate::{GenericRuleExt, GenericRuleFieldsExt};
+    use crate::{Mode, Paths, Severity};
 
     const CONTROL_FILES: [&str; 7] = [
         "tests/rules/cpp/arrays-out-of-bounds-access.yaml",
@@ -173,6 +376,63 @@ mod tests {
         "snprintf-insecure-use",
     ];
 
+    #[test]
+    fn test_matches_check_id() {
+        assert!(matches_check_id("use-of-gets", "use-of-gets"));
+        assert!(matches_check_id("use-of-gets", "tmp.tmpx8h2.use-of-gets"));
+        assert!(!matches_check_id("gets", "tmp.use-of-gets"));
+    }
+
+    #[test]
+    fn test_qualified_rule_id() {
+        let id = "buffer-overflow";
+        let file = "rules/cpp/security/buffer-overflow.yaml";
+        assert_eq!(
+            qualified_rule_id(file, Some("rules"), id),
+            "cpp.security.buffer-overflow.buffer-overflow"
+        );
+        assert_eq!(
+            qualified_rule_id(file, None, id),
+            "rules.cpp.security.buffer-overflow.buffer-overflow"
+        );
+        // the file is not in root.
+        assert_eq!(
+            qualified_rule_id(file, Some("other"), id),
+            "rules.cpp.security.buffer-overflow.buffer-overflow"
+        );
+        assert_eq!(
+            qualified_rule_id("./rules/foo.yml", None, id),
+            "rules.foo.buffer-overflow"
+        );
+        assert_eq!(
+            qualified_rule_id("/registry/rules/foo.yml", Some("/registry/"), id),
+            "rules.foo.buffer-overflow"
+        );
+    }
+
+    #[test]
+    fn test_create_index_complete() {
+        let rule_file = GenericRuleFile::from_file("tests/rules/multiple-rules.yaml").unwrap();
+        let index = rule_file.create_index_with_root(
+            "tests/rules/multiple-rules.yaml",
+            Some("tests"),
+            true,
+        );
+        let mut keys: Vec<&String> = index.keys().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "rules.multiple-rules.memcpy-insecure-use",
+                "rules.multiple-rules.potentially-uninitialized-pointer",
+                "rules.multiple-rules.snprintf-insecure-use",
+            ]
+        );
+        // without complete, the rule IDs are used.
+        let index = rule_file.create_index("tests/rules/multiple-rules.yaml", false);
+        assert!(index.contains_key("memcpy-insecure-use"));
+    }
+
     #[test]
     fn test_get_id() {
         for index in 0..CONTROL_FILES.len() {
@@ -181,4 +441,72 @@ mod tests {
             assert_eq!(id, RULE_IDS[index]);
         }
     }
+
+    #[test]
+    fn test_accessors() {
+        let rule_file = GenericRuleFile::from_file(CONTROL_FILES[4]).unwrap();
+        let rule = &rule_file.rules[0];
+
+        assert_eq!(rule.get_mode().unwrap(), Mode::Search);
+        assert_eq!(rule.get_languages().unwrap(), vec!["cpp", "c"]);
+        assert_eq!(rule.get_severity().unwrap(), Severity::Warning);
+        assert!(rule.get_message().unwrap().starts_with("Check the third"));
+        assert!(rule.get_metadata().unwrap().contains_key("category"));
+        assert!(rule.get_fix().is_err());
+    }
+
+    #[test]
+    fn test_accessor_errors() {
+        let yaml = r#"
+rules:
+- id: taint
+  mode: taint
+  languages: python
+  severity: LOW
+  message: [1]
+  paths:
+    include: ["*.py"]
+    exclude: ["tests/"]
+  options:
+    symbolic_propagation: true
+  fix: safe()
+- id: bad-mode
+  mode: 1
+  paths: "*.py"
+  options: true
+"#;
+        let rule_file = GenericRuleFile::from_yaml(yaml).unwrap();
+        let taint = &rule_file.rules[0];
+        assert_eq!(taint.get_mode().unwrap(), Mode::Taint);
+        assert_eq!(
+            taint.get_languages().unwrap_err().to_string(),
+            "The rule's `languages` field is not a list."
+        );
+        assert_eq!(
+            taint.get_severity().unwrap_err().to_string(),
+            "Unknown rule severity: `LOW`."
+        );
+        assert!(taint.get_message().is_err());
+        assert_eq!(
+            taint.get_paths().unwrap(),
+            Paths {
+                include: Some(vec!["*.py".to_string()]),
+                exclude: Some(vec!["tests/".to_string()]),
+            }
+        );
+        assert!(taint
+            .get_options()
+            .unwrap()
+            .contains_key("symbolic_propagation"));
+        assert_eq!(taint.get_fix().unwrap(), "safe()");
+
+        let bad = &rule_file.rules[1];
+        assert!(bad.get_mode().is_err());
+        assert!(bad.get_paths().is_err());
+        assert!(bad.get_options().is_err());
+        assert_eq!(
+            bad.get_metadata().unwrap_err().to_string(),
+            "The rule doesn't have a `metadata` field."
+        );
+    }
 }
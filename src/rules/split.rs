@@ -0,0 +1,156 @@
+// Split rules into one rule file per language.
+//
+// Semgrep parses every rule in the config for every target, so scanning a
+// monorepo with one combined file is slow. Splitting the rules by their
+// `languages` allows running each part of the code with only the rules for
+// its languages.
+
+use std::collections::BTreeMap;
+
+use log::error;
+
+use super::generic_rule_index::GenericRuleIndex;
+use super::policy::Policy;
+use super::rule::canonical_language;
+use super::semgrep_generic_rule::{
+    GenericRule, GenericRuleExt, GenericRuleFieldsExt, GenericRuleFile,
+};
+
+/// the name of the bucket with the multi-language rules in
+/// `SplitMode::Shared`.
+pub const SHARED_BUCKET: &str = "shared";
+
+/// what to do with rules that have more than one language.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitMode {
+    /// add the rule to the file of every language.
+    Duplicate,
+    /// add the rule to one shared file (see `SHARED_BUCKET`).
+    Shared,
+}
+
+impl GenericRuleFile {
+    /// split the rules by language. Returns a map where the key is the
+    /// canonical language name (lowercase, e.g., `python` for `py`) and the
+    /// value is a file with the rules for that language. Rules keep their
+    /// order. Rules without `languages` are skipped.
+    pub fn split_by_language(&self, mode: SplitMode) -> BTreeMap<String, GenericRuleFile> {
+        let mut buckets: BTreeMap<String, Vec<GenericRule>> = BTreeMap::new();
+
+        for rule in &self.rules {
+            let languages: Vec<String> = match rule.get_languages() {
+                Ok(langs) => langs.iter().fold(Vec::new(), |mut acc, l| {
+                    let lang = canonical_language(l);
+                    if !acc.contains(&lang) {
+                        acc.push(lang);
+                    }
+                    acc
+                }),
+                Err(e) => {
+                    error!("skipping rule {}: {}", rule.get_id().unwrap_or_default(), e);
+                    continue;
+                }
+            };
+
+            let keys = match (mode, languages.len()) {
+                (_, 0) => continue,
+                (SplitMode::Shared, n) if n > 1 => vec![SHARED_BUCKET.to_string()],
+                _ => languages,
+            };
+            for key in keys {
+                buckets.entry(key).or_default().push(rule.clone());
+            }
+        }
+
+        buckets
+            .into_iter()
+            .map(|(lang, rules)| (lang, GenericRuleFile { rules }))
+            .collect()
+    }
+}
+
+impl GenericRuleIndex {
+    // split all the rules in the index by language. The rules in each file are
+    // sorted by their ID in the index. See GenericRuleFile::split_by_language.
+    pub fn split_by_language(&self, mode: SplitMode) -> BTreeMap<String, GenericRuleFile> {
+        let mut ids = self.get_ids();
+        ids.sort();
+        self.create_policy(&ids).split_by_language(mode)
+    }
+}
+
+impl Policy {
+    // split the policy's rules by language. See
+    // GenericRuleFile::split_by_language.
+    pub fn split_by_language(
+        &self,
+        ri: &GenericRuleIndex,
+        mode: SplitMode,
+    ) -> BTreeMap<String, GenericRuleFile> {
+        ri.create_policy(&self.get_rules()).split_by_language(mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIXED: &str = "tests/split/mixed.yaml";
+
+    fn ids(files: &BTreeMap<String, GenericRuleFile>, lang: &str) -> Vec<String> {
+        files[lang]
+            .rules
+            .iter()
+            .map(|r| r.get_id().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_duplicate() {
+        let rule_file = GenericRuleFile::from_file(MIXED).unwrap();
+        let files = rule_file.split_by_language(SplitMode::Duplicate);
+
+        assert_eq!(
+            files.keys().collect::<Vec<&String>>(),
+            vec!["c", "cpp", "python"]
+        );
+        assert_eq!(ids(&files, "c"), vec!["c-only", "c-and-cpp"]);
+        assert_eq!(ids(&files, "cpp"), vec!["c-and-cpp", "cpp-alias"]);
+        // language names are case insensitive and aliases are in the same
+        // file as the canonical name.
+        assert_eq!(
+            ids(&files, "python"),
+            vec!["python-only", "python-upper", "python-alias"]
+        );
+    }
+
+    #[test]
+    fn test_split_shared() {
+        let rule_file = GenericRuleFile::from_file(MIXED).unwrap();
+        let files = rule_file.split_by_language(SplitMode::Shared);
+
+        assert_eq!(
+            files.keys().collect::<Vec<&String>>(),
+            vec!["c", "cpp", "python", SHARED_BUCKET]
+        );
+        assert_eq!(ids(&files, "c"), vec!["c-only"]);
+        // `c++` and `cpp` are the same language so the rule is not shared.
+        assert_eq!(ids(&files, "cpp"), vec!["cpp-alias"]);
+        assert_eq!(ids(&files, SHARED_BUCKET), vec!["c-and-cpp"]);
+    }
+
+    #[test]
+    fn test_split_index_and_policy() {
+        let ri = GenericRuleIndex::from_path_simple(MIXED).unwrap();
+        let files = ri.split_by_language(SplitMode::Duplicate);
+        assert_eq!(ids(&files, "c"), vec!["c-and-cpp", "c-only"]);
+
+        let policy = Policy::new(
+            "python".to_string(),
+            vec!["python-only".to_string(), "c-only".to_string()],
+        );
+        let files = policy.split_by_language(&ri, SplitMode::Shared);
+        assert_eq!(ids(&files, "python"), vec!["python-only"]);
+        assert_eq!(ids(&files, "c"), vec!["c-only"]);
+    }
+}
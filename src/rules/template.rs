@@ -0,0 +1,276 @@
+// Rule templates.
+//
+// A template is a rule with a `template` key that maps variable names to a
+// value or a list of values. The template is expanded into one rule for every
+// combination of values and `{{name}}` is replaced by the variable's value in
+// every string in the rule (including the ID).
+//
+// rules:
+//   - id: insecure-{{func}}
+//     template:
+//       func: [strcpy, strcat]
+//       lang: c
+//     languages: ["{{lang}}"]
+//     pattern: "{{func}}(...)"
+//     ...
+//
+// If the ID doesn't use a variable, the values of the variables with more
+// than one value are appended to it (e.g., `insecure-use` becomes
+// `insecure-use-strcpy`) so each generated rule has a unique ID. Placeholders
+// that are not variables (e.g., `{{ $X }}` in a pattern for a template
+// language) are not changed.
+//
+// The number of rules is the product of the number of values of every
+// variable so it's limited (DEFAULT_MAX_TEMPLATE_RULES by default).
+
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+use serde_yaml::{Mapping, Value};
+
+use super::semgrep_generic_rule::GenericRule;
+use crate::{Error, Result};
+
+const TEMPLATE_KEY: &str = "template";
+
+/// the maximum number of rules a template can expand to, if not set in
+/// `IndexOptions::max_template_rules`.
+pub(crate) const DEFAULT_MAX_TEMPLATE_RULES: usize = 1000;
+
+/// return true if the rule is a template.
+pub(crate) fn is_template(rule: &GenericRule) -> bool {
+    rule.contains_key(TEMPLATE_KEY)
+}
+
+/// expand a template into concrete rules. Rules that are not templates are
+/// returned as-is. Returns an error if the template expands to more than
+/// max_rules rules.
+pub(crate) fn expand_template(rule: &GenericRule, max_rules: usize) -> Result<Vec<GenericRule>> {
+    let vars = match rule.get(TEMPLATE_KEY) {
+        None => return Ok(vec![rule.clone()]),
+        Some(Value::Mapping(vars)) => read_vars(vars)?,
+        Some(_) => return Error::wrap_str("The rule's `template` field is not a mapping."),
+    };
+
+    let count = vars.iter().fold(1_usize, |count, (_, values)| {
+        count.saturating_mul(values.len())
+    });
+    if count > max_rules {
+        return Error::wrap_string(format!(
+            "The template expands to {} rules, the maximum is {}.",
+            count, max_rules
+        ));
+    }
+
+    let mut body = rule.clone();
+    body.remove(TEMPLATE_KEY);
+    let id = match body.get("id") {
+        Some(Value::String(id)) => id.clone(),
+        _ => return Error::wrap_str("The template doesn't have an `id` field."),
+    };
+
+    let re = placeholder_regex();
+    let id_has_vars = vars.iter().any(|(name, _)| uses_var(re, &id, name));
+
+    let mut rules: Vec<GenericRule> = Vec::new();
+    for combination in combinations(&vars) {
+        let mut expanded = substitute_mapping(re, &body, &combination);
+        if !id_has_vars {
+            let suffix: Vec<&str> = vars
+                .iter()
+                .zip(&combination)
+                .filter(|((_, values), _)| values.len() > 1)
+                .map(|(_, (_, value))| value.as_str())
+                .collect();
+            if !suffix.is_empty() {
+                let new_id = format!("{}-{}", id, suffix.join("-"));
+                expanded.insert(Value::from("id"), Value::from(new_id));
+            }
+        }
+        rules.push(expanded);
+    }
+    Ok(rules)
+}
+
+// read the variables and their values in order.
+fn read_vars(vars: &Mapping) -> Result<Vec<(String, Vec<String>)>> {
+    let mut results: Vec<(String, Vec<String>)> = Vec::new();
+    for (name, value) in vars {
+        let name = match name.as_str() {
+            Some(n) => n.to_string(),
+            None => return Error::wrap_str("Template variable names must be strings."),
+        };
+        let values = match value {
+            Value::Sequence(items) => items
+                .iter()
+                .map(|v| scalar(v, &name))
+                .collect::<Result<Vec<String>>>()?,
+            v => vec![scalar(v, &name)?],
+        };
+        if values.is_empty() {
+            return Error::wrap_string(format!(
+                "Template variable `{}` doesn't have values.",
+                name
+            ));
+        }
+        results.push((name, values));
+    }
+    Ok(results)
+}
+
+fn scalar(value: &Value, name: &str) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Error::wrap_string(format!(
+            "The values of template variable `{}` must be scalars.",
+            name
+        )),
+    }
+}
+
+// return every combination of values in order, the last variable changes
+// first. Each combination has one (name, value) per variable.
+fn combinations(vars: &[(String, Vec<String>)]) -> Vec<Vec<(String, String)>> {
+    let mut results: Vec<Vec<(String, String)>> = vec![vec![]];
+    for (name, values) in vars {
+        results = results
+            .into_iter()
+            .flat_map(|prefix| {
+                values.iter().map(move |value| {
+                    let mut combination = prefix.clone();
+                    combination.push((name.clone(), value.clone()));
+                    combination
+                })
+            })
+            .collect();
+    }
+    results
+}
+
+// the regex for `{{name}}`, compiled once.
+fn placeholder_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // the pattern is a valid constant so this cannot fail.
+    RE.get_or_init(|| {
+        Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_-]*)\s*\}\}").expect("invalid placeholder regex")
+    })
+}
+
+fn uses_var(re: &Regex, text: &str, name: &str) -> bool {
+    re.captures_iter(text).any(|c| &c[1] == name)
+}
+
+// replace the variables in all the strings in value.
+fn substitute(re: &Regex, value: &Value, combination: &[(String, String)]) -> Value {
+    match value {
+        Value::String(s) => Value::String(
+            re.replace_all(s, |c: &Captures| {
+                combination
+                    .iter()
+                    .find(|(name, _)| name == &c[1])
+                    .map(|(_, value)| value.clone())
+                    .unwrap_or_else(|| c[0].to_string())
+            })
+            .into_owned(),
+        ),
+        Value::Sequence(items) => Value::Sequence(
+            items
+                .iter()
+                .map(|i| substitute(re, i, combination))
+                .collect(),
+        ),
+        Value::Mapping(map) => Value::Mapping(substitute_mapping(re, map, combination)),
+        v => v.clone(),
+    }
+}
+
+// same as substitute for the values of a mapping. Keys are not changed.
+fn substitute_mapping(re: &Regex, map: &Mapping, combination: &[(String, String)]) -> Mapping {
+    map.iter()
+        .map(|(k, v)| (k.clone(), substitute(re, v, combination)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::semgrep_generic_rule::{
+        GenericRuleExt, GenericRuleFieldsExt, GenericRuleFile,
+    };
+
+    const TEMPLATE_FILE: &str = "tests/templates/insecure-functions.yaml";
+
+    #[test]
+    fn test_expand_template() {
+        let rule_file = GenericRuleFile::from_file(TEMPLATE_FILE).unwrap();
+        assert!(is_template(&rule_file.rules[0]));
+
+        let rules = expand_template(&rule_file.rules[0], DEFAULT_MAX_TEMPLATE_RULES).unwrap();
+        let ids: Vec<&str> = rules.iter().map(|r| r.get_id().unwrap()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "insecure-strcpy-c",
+                "insecure-strcpy-cpp",
+                "insecure-strcat-c",
+                "insecure-strcat-cpp"
+            ]
+        );
+
+        let strcat = &rules[3];
+        assert!(!is_template(strcat));
+        assert_eq!(strcat.get_languages().unwrap(), vec!["cpp"]);
+        assert_eq!(
+            strcat.get_message().unwrap(),
+            "strcat is insecure, $DST can overflow."
+        );
+        // metavariables and unknown placeholders are not changed.
+        assert_eq!(
+            strcat.get("pattern").unwrap().as_str(),
+            Some("strcat($DST, {{ not_a_var }})")
+        );
+    }
+
+    #[test]
+    fn test_generated_ids() {
+        // the ID doesn't use a variable.
+        let rule_file = GenericRuleFile::from_file(TEMPLATE_FILE).unwrap();
+        let rules = expand_template(&rule_file.rules[1], DEFAULT_MAX_TEMPLATE_RULES).unwrap();
+        let ids: Vec<&str> = rules.iter().map(|r| r.get_id().unwrap()).collect();
+        assert_eq!(ids, vec!["dangerous-call-gets", "dangerous-call-system"]);
+
+        // rules that are not templates are not changed.
+        let rules = expand_template(&rule_file.rules[2], DEFAULT_MAX_TEMPLATE_RULES).unwrap();
+        assert_eq!(rules, vec![rule_file.rules[2].clone()]);
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        for yaml in [
+            "id: foo\ntemplate: [a, b]",
+            "id: foo\ntemplate:\n  func: []",
+            "id: foo\ntemplate:\n  func: [{a: b}]",
+            "template:\n  func: a",
+        ] {
+            let rule: GenericRule = serde_yaml::from_str(yaml).unwrap();
+            assert!(
+                expand_template(&rule, DEFAULT_MAX_TEMPLATE_RULES).is_err(),
+                "{}",
+                yaml
+            );
+        }
+    }
+
+    #[test]
+    fn test_max_rules() {
+        let rule_file = GenericRuleFile::from_file(TEMPLATE_FILE).unwrap();
+        // the first template expands to 4 rules.
+        assert_eq!(expand_template(&rule_file.rules[0], 4).unwrap().len(), 4);
+        let err = expand_template(&rule_file.rules[0], 3).err().unwrap();
+        assert!(err.to_string().contains("expands to 4 rules"), "{}", err);
+        // rules that are not templates are always returned.
+        assert_eq!(expand_template(&rule_file.rules[2], 0).unwrap().len(), 1);
+    }
+}
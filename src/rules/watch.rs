@@ -0,0 +1,439 @@
+// Reload the rule and policy indexes when the files change.
+//
+// IndexWatcher owns a GenericRuleIndex and a PolicyIndex created from a set of
+// paths. When a file under those paths is created, modified or removed, both
+// indexes are reloaded with an IndexCache so only the changed files are
+// parsed. All policies (including `all`) are populated again from the new
+// rule index. Subscribers receive an IndexUpdate with the rules and policies
+// that were added, removed or modified.
+//
+// Readers get the current indexes with `rule_index()` and `policy_index()`.
+// These are cheap snapshots (Arc), a reload replaces them and doesn't change
+// the snapshots that were already returned.
+//
+// If a reload fails (e.g., all rule files were removed), the error is logged
+// and the old indexes are kept.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use log::error;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::cache::IndexCache;
+use super::generic_rule_index::{GenericRuleIndex, IndexOptions};
+use super::load_report::LoadReport;
+use super::policy::PolicyIndex;
+use crate::error::{Error, Result};
+
+// wait this long after a file event for more events before reloading. Editors
+// and `git checkout` change many files at once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// ----- START WatchConfig
+
+/// the paths and options used to create (and reload) the indexes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WatchConfig {
+    /// paths to the rule files and directories.
+    pub rule_paths: Vec<String>,
+    /// paths to the policy files and directories. If empty, the policy index
+    /// only has the `all` policy.
+    pub policy_paths: Vec<String>,
+    /// file extensions to index, see `find_files`.
+    pub include: Option<Vec<String>>,
+    /// skip files that end in these, see `find_files`.
+    pub exclude: Option<Vec<String>>,
+    /// options for the rule index. `strict` also applies to the policies.
+    pub options: IndexOptions,
+}
+
+// ----- END WatchConfig
+
+// ----- START IndexUpdate
+
+/// what changed in a reload. IDs are sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexUpdate {
+    pub added_rules: Vec<String>,
+    pub removed_rules: Vec<String>,
+    /// rules with a different content hash.
+    pub modified_rules: Vec<String>,
+    pub added_policies: Vec<String>,
+    pub removed_policies: Vec<String>,
+    /// policies with different rules or different content. E.g., a policy
+    /// that has a modified rule or `all` when any rule changes.
+    pub modified_policies: Vec<String>,
+    /// the files that were loaded and skipped in this reload.
+    pub report: LoadReport,
+}
+
+impl IndexUpdate {
+    /// return true if no rules or policies changed.
+    pub fn is_empty(&self) -> bool {
+        self.added_rules.is_empty()
+            && self.removed_rules.is_empty()
+            && self.modified_rules.is_empty()
+            && self.added_policies.is_empty()
+            && self.removed_policies.is_empty()
+            && self.modified_policies.is_empty()
+    }
+}
+
+// ----- END IndexUpdate
+
+// ----- START IndexWatcher
+
+/// a rule index and a policy index that are reloaded when their files change.
+pub struct IndexWatcher {
+    inner: Arc<Inner>,
+    // dropping the watcher stops the reload thread.
+    watcher: Option<RecommendedWatcher>,
+}
+
+// the state shared with the reload thread.
+struct Inner {
+    config: WatchConfig,
+    indexes: RwLock<(Arc<GenericRuleIndex>, Arc<PolicyIndex>)>,
+    cache: Mutex<IndexCache>,
+    subscribers: Mutex<Vec<Sender<IndexUpdate>>>,
+}
+
+impl IndexWatcher {
+    /// create the indexes from the paths in config. Call `start` to reload
+    /// them when the files change.
+    pub fn new(config: WatchConfig) -> Result<IndexWatcher> {
+        let mut cache = IndexCache::new();
+        let (ri, pi, _) = load_indexes(&config, &mut cache)?;
+        let inner = Inner {
+            config,
+            indexes: RwLock::new((Arc::new(ri), Arc::new(pi))),
+            cache: Mutex::new(cache),
+            subscribers: Mutex::new(Vec::new()),
+        };
+        Ok(IndexWatcher {
+            inner: Arc::new(inner),
+            watcher: None,
+        })
+    }
+
+    /// start watching the rule and policy paths. Does nothing if the watcher
+    /// has already started.
+    pub fn start(&mut self) -> Result<()> {
+        if self.watcher.is_some() {
+            return Ok(());
+        }
+
+        let (tx, rx) = channel::<notify::Result<notify::Event>>();
+        let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())
+            .map_err(|e| Error::new(format!("Error creating the file watcher: {}", e)))?;
+
+        let config = &self.inner.config;
+        for path in config.rule_paths.iter().chain(config.policy_paths.iter()) {
+            watcher
+                .watch(Path::new(path), RecursiveMode::Recursive)
+                .map_err(|e| Error::new(format!("Error watching {}: {}", path, e)))?;
+        }
+
+        let inner = Arc::clone(&self.inner);
+        thread::spawn(move || watch_loop(inner, rx));
+        self.watcher = Some(watcher);
+        Ok(())
+    }
+
+    /// return the current rule index.
+    pub fn rule_index(&self) -> Arc<GenericRuleIndex> {
+        Arc::clone(&self.inner.indexes.read().unwrap().0)
+    }
+
+    /// return the current policy index.
+    pub fn policy_index(&self) -> Arc<PolicyIndex> {
+        Arc::clone(&self.inner.indexes.read().unwrap().1)
+    }
+
+    /// return a channel that receives an IndexUpdate after every reload that
+    /// changed the indexes.
+    pub fn subscribe(&self) -> Receiver<IndexUpdate> {
+        let (tx, rx) = channel();
+        self.inner.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// reload the indexes now and notify the subscribers if anything changed.
+    /// The watcher calls this when the files change.
+    pub fn reload(&self) -> Result<IndexUpdate> {
+        self.inner.reload()
+    }
+}
+
+impl Inner {
+    fn reload(&self) -> Result<IndexUpdate> {
+        // only one reload at a time, the cache lock is held until the new
+        // indexes are stored.
+        let mut cache = self.cache.lock().unwrap();
+        let (ri, pi, report) = load_indexes(&self.config, &mut cache)?;
+
+        let mut indexes = self.indexes.write().unwrap();
+        let mut update = diff_indexes(&indexes.0, &indexes.1, &ri, &pi);
+        update.report = report;
+        *indexes = (Arc::new(ri), Arc::new(pi));
+        drop(indexes);
+
+        if !update.is_empty() {
+            // remove the subscribers that have dropped their receiver.
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|s| s.send(update.clone()).is_ok());
+        }
+        Ok(update)
+    }
+}
+
+// reload the indexes after file events until the watcher is dropped.
+fn watch_loop(inner: Arc<Inner>, rx: Receiver<notify::Result<notify::Event>>) {
+    while let Ok(event) = rx.recv() {
+        if !is_change(&event) {
+            continue;
+        }
+        // wait for the rest of the events.
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        if let Err(e) = inner.reload() {
+            error!("Error reloading the indexes: {}", e);
+        }
+    }
+}
+
+// return true if the event created, modified or removed a file. Metadata
+// changes are ignored, reading the files can change their access time.
+fn is_change(event: &notify::Result<notify::Event>) -> bool {
+    match event {
+        Ok(event) => match event.kind {
+            EventKind::Modify(ModifyKind::Metadata(_)) => false,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => true,
+            EventKind::Any => true,
+            _ => false,
+        },
+        Err(e) => {
+            error!("Error watching the files: {}", e);
+            false
+        }
+    }
+}
+
+// ----- END IndexWatcher
+
+// ----- START helpers
+
+// create the rule and policy indexes from the paths in config.
+fn load_indexes(
+    config: &WatchConfig,
+    cache: &mut IndexCache,
+) -> Result<(GenericRuleIndex, PolicyIndex, LoadReport)> {
+    let include: Option<Vec<&str>> = config
+        .include
+        .as_ref()
+        .map(|v| v.iter().map(|s| s.as_str()).collect());
+    let exclude: Option<Vec<&str>> = config
+        .exclude
+        .as_ref()
+        .map(|v| v.iter().map(|s| s.as_str()).collect());
+
+    let rule_paths: Vec<&str> = config.rule_paths.iter().map(|s| s.as_str()).collect();
+    let (ri, mut report) = GenericRuleIndex::from_paths_cached(
+        rule_paths,
+        include.clone(),
+        exclude.clone(),
+        &config.options,
+        cache,
+    )?;
+
+    if config.policy_paths.is_empty() {
+        let pi = PolicyIndex::empty(&ri)?;
+        return Ok((ri, pi, report));
+    }
+    let policy_paths: Vec<&str> = config.policy_paths.iter().map(|s| s.as_str()).collect();
+    let (pi, policy_report) = PolicyIndex::from_paths_cached(
+        policy_paths,
+        include,
+        exclude,
+        &ri,
+        config.options.strict,
+        cache,
+    )?;
+    report.loaded.extend(policy_report.loaded);
+    report.errors.extend(policy_report.errors);
+    Ok((ri, pi, report))
+}
+
+// compare the old and new indexes.
+fn diff_indexes(
+    old_ri: &GenericRuleIndex,
+    old_pi: &PolicyIndex,
+    new_ri: &GenericRuleIndex,
+    new_pi: &PolicyIndex,
+) -> IndexUpdate {
+    let (added_rules, removed_rules, modified_rules) =
+        diff_maps(&old_ri.get_hashes(), &new_ri.get_hashes());
+
+    // a policy is the same if it has the same rules and content.
+    let policies = |pi: &PolicyIndex| -> HashMap<String, (Vec<String>, String)> {
+        pi.get_index()
+            .into_iter()
+            .map(|(name, p)| (name, (p.get_rules(), p.get_content())))
+            .collect()
+    };
+    let (added_policies, removed_policies, modified_policies) =
+        diff_maps(&policies(old_pi), &policies(new_pi));
+
+    IndexUpdate {
+        added_rules,
+        removed_rules,
+        modified_rules,
+        added_policies,
+        removed_policies,
+        modified_policies,
+        report: LoadReport::default(),
+    }
+}
+
+// return the sorted keys that were added, removed and modified.
+fn diff_maps<V: PartialEq>(
+    old: &HashMap<String, V>,
+    new: &HashMap<String, V>,
+) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let (mut added, mut removed, mut modified) = (Vec::new(), Vec::new(), Vec::new());
+    for key in keys {
+        match (old.get(key), new.get(key)) {
+            (None, Some(_)) => added.push(key.clone()),
+            (Some(_), None) => removed.push(key.clone()),
+            (Some(o), Some(n)) if o != n => modified.push(key.clone()),
+            _ => {}
+        }
+    }
+    (added, removed, modified)
+}
+
+// ----- END helpers
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const RULE: &str = "rules:
+  - id: rule-1
+    message: rule 1
+    severity: ERROR
+    languages: [python]
+    pattern: eval(...)
+";
+
+    const POLICY: &str = "name: policy-1
+rules:
+  - rule-1
+";
+
+    // create a directory with rules/ and policies/ and return the config.
+    fn setup() -> (tempfile::TempDir, WatchConfig) {
+        let dir = tempfile::Builder::new().prefix("watch").tempdir().unwrap();
+        fs::create_dir(dir.path().join("rules")).unwrap();
+        fs::create_dir(dir.path().join("policies")).unwrap();
+        fs::write(dir.path().join("rules/rule-1.yaml"), RULE).unwrap();
+        fs::write(dir.path().join("policies/policy-1.yaml"), POLICY).unwrap();
+
+        let path = |p: &str| dir.path().join(p).to_str().unwrap().to_string();
+        let config = WatchConfig {
+            rule_paths: vec![path("rules")],
+            policy_paths: vec![path("policies")],
+            ..Default::default()
+        };
+        (dir, config)
+    }
+
+    #[test]
+    fn test_reload() {
+        let (dir, config) = setup();
+        let watcher = IndexWatcher::new(config).unwrap();
+        let updates = watcher.subscribe();
+        let before = watcher.rule_index();
+
+        // nothing changed.
+        assert!(watcher.reload().unwrap().is_empty());
+        assert!(updates.try_recv().is_err());
+
+        // modify rule-1 and add rule-2.
+        fs::write(
+            dir.path().join("rules/rule-1.yaml"),
+            RULE.replace("eval(...)", "os.system(...)"),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("rules/rule-2.yaml"),
+            RULE.replace("rule-1", "rule-2"),
+        )
+        .unwrap();
+        let update = watcher.reload().unwrap();
+        assert_eq!(update.added_rules, ["rule-2"]);
+        assert_eq!(update.modified_rules, ["rule-1"]);
+        assert_eq!(update.modified_policies, ["all", "policy-1"]);
+        assert_eq!(updates.try_recv().unwrap(), update);
+
+        // the old snapshot is not changed.
+        assert_eq!(before.len(), 1);
+        assert_eq!(watcher.rule_index().len(), 2);
+        let all = watcher.policy_index().get_policy("all").unwrap();
+        assert!(all.get_content().contains("os.system(...)"));
+
+        // remove rule-2 and add a policy.
+        fs::remove_file(dir.path().join("rules/rule-2.yaml")).unwrap();
+        fs::write(
+            dir.path().join("policies/policy-2.yaml"),
+            POLICY.replace("policy-1", "policy-2"),
+        )
+        .unwrap();
+        let update = watcher.reload().unwrap();
+        assert_eq!(update.removed_rules, ["rule-2"]);
+        assert_eq!(update.added_policies, ["policy-2"]);
+        assert_eq!(update.modified_policies, ["all"]);
+    }
+
+    // the old indexes are kept if the reload fails.
+    #[test]
+    fn test_reload_error() {
+        let (dir, config) = setup();
+        let watcher = IndexWatcher::new(config).unwrap();
+        fs::remove_file(dir.path().join("rules/rule-1.yaml")).unwrap();
+        assert!(watcher.reload().is_err());
+        assert_eq!(watcher.rule_index().get_ids(), ["rule-1"]);
+    }
+
+    #[test]
+    fn test_watch() {
+        let (dir, config) = setup();
+        let mut watcher = IndexWatcher::new(config).unwrap();
+        let updates = watcher.subscribe();
+        watcher.start().unwrap();
+
+        fs::write(
+            dir.path().join("rules/rule-2.yaml"),
+            RULE.replace("rule-1", "rule-2"),
+        )
+        .unwrap();
+        let update = updates.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(update.added_rules, ["rule-2"]);
+        assert_eq!(watcher.rule_index().len(), 2);
+    }
+}
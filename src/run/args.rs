@@ -25,6 +25,9 @@ pub struct Args {
     pub paths: Vec<String>,
     /// the output format
     pub output_format: OutputFormat,
+    /// validate the rules against the rule schema before running Semgrep,
+    /// default is `false`.
+    validate: bool,
 }
 
 impl Args {
@@ -48,6 +51,7 @@ impl Args {
             metrics: Metrics::from_bool(metrics),
             output_format,
             extra,
+            validate: false,
         }
     }
 
@@ -60,6 +64,7 @@ impl Args {
             metrics: Metrics::Off,
             output_format: OutputFormat::JSON,
             extra: None,
+            validate: false,
         }
     }
 
@@ -74,6 +79,23 @@ impl Args {
         self.metrics = Metrics::Off;
     }
 
+    /// validate the rules against the rule schema before running Semgrep.
+    /// Execution returns an `Error::SchemaError` with all the violations if
+    /// the rules are not valid.
+    pub fn enable_validation(&mut self) {
+        self.validate = true;
+    }
+
+    /// do not validate the rules before running Semgrep. This is the default.
+    pub fn disable_validation(&mut self) {
+        self.validate = false;
+    }
+
+    /// return true if the rules will be validated before running Semgrep.
+    pub fn validation_enabled(&self) -> bool {
+        self.validate
+    }
+
     /// add extra arguments to the Semgrep CLI. This will be appended to the
     /// current extra arguments.
     pub fn add_extra(&mut self, extra: Vec<String>) {
@@ -2,6 +2,7 @@ use tempfile::NamedTempFile;
 
 use crate::{
     error::{Error, Result},
+    rules::schema,
     Args, GenericRuleFile,
 };
 
@@ -14,6 +15,12 @@ pub(crate) fn internal_exec(args: &Args) -> Result<std::process::Output> {
     // result here.
     GenericRuleFile::from_yaml(&args.rules)?;
 
+    // validate the rules against the schema if requested. This catches
+    // problems like missing keys before Semgrep is started.
+    if args.validation_enabled() {
+        schema::check_yaml(&args.rules)?;
+    }
+
     // create a temp file and write the rule string to it.
     // temp files created this way might be destroyed but we only want the file
     // to be valid for a few minutes at most.
@@ -68,4 +75,19 @@ mod tests {
             true => println!("semgrep is installed"),
         }
     }
+
+    // invalid rules should be rejected before Semgrep is started.
+    #[test]
+    fn test_validation_before_exec() {
+        let rules = "rules:\n- id: no-message\n  pattern: foo()\n  languages: [python]\n  severity: ERROR\n";
+        let mut args = Args::default(rules.to_string(), vec![".".to_string()]);
+        args.enable_validation();
+
+        match internal_exec(&args) {
+            Err(Error::SchemaError(violations)) => {
+                assert_eq!(violations[0].rule_id.as_deref(), Some("no-message"))
+            }
+            _ => panic!("expected a schema error"),
+        }
+    }
 }
@@ -57,11 +57,6 @@ pub fn find_files(
     include: &Option<Vec<&str>>,
     exclude: &Option<Vec<&str>>,
 ) -> Vec<String> {
-    // use the default values if include and exclude are not provided.
-
-    let include_extensions = include.clone().unwrap_or_else(|| rule_extensions());
-    let exclude_extensions = exclude.clone().unwrap_or_else(|| test_extensions());
-
     let mut results: Vec<String> = Vec::new();
 
     let walker = WalkDir::new(path).into_iter();
@@ -72,30 +67,38 @@ pub fn find_files(
         .filter_map(|e| e.ok())
     {
         let file_path = entry.path();
-
-        // get the extension
-        if let Some(extension) = file_path.extension() {
-            // convert the extension from &OsStr to &str
-            if let Some(ext_str) = extension.to_str() {
-                // check if the file is a rule
-                if include_extensions.contains(&ext_str) {
-                    // convert the file path to a string
-                    let file_path_string = file_path.to_string_lossy();
-
-                    // check if the file ends with exclude_extensions
-                    for excluded in &exclude_extensions {
-                        if file_path_string.ends_with(excluded) {
-                            continue;
-                        }
-                    }
-                    results.push(file_path.to_string_lossy().as_ref().to_string());
-                }
-            }
+        if is_included(file_path, include, exclude) {
+            results.push(file_path.to_string_lossy().to_string());
         }
     }
     results
 }
 
+// returns true if the file has one of the extensions in include and doesn't
+// end in any of exclude. Uses the default values if include and exclude are
+// not provided.
+pub(crate) fn is_included(
+    file_path: &Path,
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> bool {
+    let include_extensions = include.clone().unwrap_or_else(|| rule_extensions());
+    let exclude_extensions = exclude.clone().unwrap_or_else(|| test_extensions());
+
+    // get the extension and convert it from &OsStr to &str
+    match file_path.extension().and_then(|e| e.to_str()) {
+        // check if the file is a rule
+        Some(ext_str) if include_extensions.contains(&ext_str) => {
+            // skip the file if it ends with any of exclude_extensions
+            let file_path_string = file_path.to_string_lossy();
+            !exclude_extensions
+                .iter()
+                .any(|excluded| file_path_string.ends_with(excluded))
+        }
+        _ => false,
+    }
+}
+
 // rule_extensions returns the default file extensions for Semgrep rules.
 fn rule_extensions() -> Vec<&'static str> {
     vec!["yml", "yaml"]
@@ -111,8 +114,53 @@ pub fn find_files_simple(path: &str) -> Vec<String> {
     find_files(path, &None, &None)
 }
 
+// find the files in all paths (see find_files). The files in each path are
+// sorted and the paths keep their order so the result doesn't depend on the
+// file system or the number of threads.
+pub(crate) fn find_files_in_paths(
+    paths: Vec<&str>,
+    include: &Option<Vec<&str>>,
+    exclude: &Option<Vec<&str>>,
+) -> Vec<String> {
+    map_ordered(paths, |p| {
+        let mut files = find_files(p, include, exclude);
+        files.sort();
+        files
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
 // ----- END find_rules
 
+// ----- START map_ordered
+
+// apply f to every item and return the results in the same order as items.
+// With the `parallel` feature, the items are processed in parallel.
+#[cfg(feature = "parallel")]
+pub(crate) fn map_ordered<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync + Send,
+{
+    use rayon::prelude::*;
+    items.into_par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn map_ordered<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync + Send,
+{
+    items.into_iter().map(f).collect()
+}
+
+// ----- END map_ordered
+
 // ----- START read_file_to_string
 
 // read a file and return a String.
@@ -168,6 +216,14 @@ mod tests {
         assert_eq!(results.sort(), control.sort());
     }
 
+    // files that end in one of the default test extensions should be skipped.
+    #[test]
+    fn test_find_files_exclude() {
+        let results = find_files("tests", &None, &None);
+        assert!(results.contains(&"tests/rules/multiple-rules.yaml".to_string()));
+        assert!(!results.contains(&"tests/not-a-rule-1.test.yaml".to_string()));
+    }
+
     // test for find_files_simple().
     #[test]
     fn test_find_files_simple() {
@@ -177,6 +233,30 @@ mod tests {
         assert_eq!(results.sort(), control.sort());
     }
 
+    // files are sorted in each path and paths keep their order.
+    #[test]
+    fn test_find_files_in_paths() {
+        let results =
+            find_files_in_paths(vec!["tests/rules/cpp", "tests/duplicates"], &None, &None);
+        assert_eq!(results.len(), 9);
+        assert_eq!(
+            results[0],
+            "tests/rules/cpp/arrays-out-of-bounds-access.yaml"
+        );
+        assert_eq!(
+            results[7..],
+            ["tests/duplicates/a.yaml", "tests/duplicates/b/c.yaml"]
+        );
+    }
+
+    // the results are in order with or without the `parallel` feature.
+    #[test]
+    fn test_map_ordered() {
+        let items: Vec<usize> = (0..1000).collect();
+        let results = map_ordered(items.clone(), |i| i * 2);
+        assert_eq!(results, items.iter().map(|i| i * 2).collect::<Vec<usize>>());
+    }
+
     // test for read_file_to_string().
     #[test]
     fn test_read_file_to_string() {
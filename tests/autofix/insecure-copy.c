@@ -0,0 +1,10 @@
+#include <stdio.h>
+#include <string.h>
+
+void copy(char *src) {
+    char buf[16];
+    // ruleid: use-strncpy
+    strcpy(buf, src);
+    // ruleid: use-fgets
+    gets(buf);
+}
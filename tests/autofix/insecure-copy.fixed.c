@@ -0,0 +1,10 @@
+#include <stdio.h>
+#include <string.h>
+
+void copy(char *src) {
+    char buf[16];
+    // ruleid: use-strncpy
+    strncpy(buf, src, sizeof(buf));
+    // ruleid: use-fgets
+    fgets(buf, sizeof(buf), stdin);
+}
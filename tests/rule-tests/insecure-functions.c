@@ -0,0 +1,21 @@
+#include <stdio.h>
+#include <string.h>
+
+void test(char *src, char *buf, char *other) {
+    // ruleid: use-of-strcpy
+    strcpy(buf, src);
+
+    // ruleid: use-of-gets
+    gets(buf);
+    // ok: use-of-gets
+    fgets(buf, sizeof(buf), stdin);
+
+    char local[10];
+    // todook: use-of-strcpy
+    strcpy(local, "constant");
+
+    // ruleid: use-of-gets
+    gets(other);
+    // todoruleid: use-of-strcpy
+    strncpy(buf, src, sizeof(buf));
+}